@@ -0,0 +1,241 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitBool, LitStr};
+
+/// Implements `IoticsFeed` for a struct with named fields, each field being a value of the feed.
+///
+/// ```ignore
+/// #[derive(IoticsFeed)]
+/// #[iotics(id = "weather", label = "Weather")]
+/// struct Weather {
+///     #[iotics(comment = "Air temperature", unit = "http://qudt.org/vocab/unit/DEG_C")]
+///     temperature: f64,
+///     #[iotics(label = "wind_speed", data_type = "decimal")]
+///     wind: Option<f32>,
+///     #[iotics(skip)]
+///     station_id: String,
+/// }
+/// ```
+///
+/// Struct attributes: `id` (the snake case struct name by default), `label`, `lang` (`en` by default)
+/// and `store_last` (`true` by default).
+/// Field attributes: `label` (the field name by default), `comment`, `unit`, `data_type`
+/// (inferred from the field type by default) and `skip`.
+#[proc_macro_derive(IoticsFeed, attributes(iotics))]
+pub fn derive_iotics_feed(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[derive(Default)]
+struct FeedAttributes {
+    id: Option<LitStr>,
+    label: Option<LitStr>,
+    lang: Option<LitStr>,
+    store_last: Option<LitBool>,
+}
+
+#[derive(Default)]
+struct FieldAttributes {
+    label: Option<LitStr>,
+    comment: Option<LitStr>,
+    unit: Option<LitStr>,
+    data_type: Option<LitStr>,
+    skip: bool,
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "IoticsFeed can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "IoticsFeed can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut attributes = FeedAttributes::default();
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("iotics"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("id") {
+                attributes.id = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("label") {
+                attributes.label = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("lang") {
+                attributes.lang = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("store_last") {
+                attributes.store_last = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("unknown iotics feed attribute"));
+            }
+
+            Ok(())
+        })?;
+    }
+
+    let feed_id = attributes
+        .id
+        .map(|id| id.value())
+        .unwrap_or_else(|| to_snake_case(&name.to_string()));
+    let store_last = attributes
+        .store_last
+        .map(|store_last| store_last.value)
+        .unwrap_or(true);
+    let lang = attributes
+        .lang
+        .map(|lang| lang.value())
+        .unwrap_or_else(|| "en".to_string());
+    let properties = match attributes.label {
+        Some(label) => quote! {
+            vec![::iotics_connector_engine::client::properties::PropertyBuilder::build_label(#lang, #label)]
+        },
+        None => quote! { Vec::new() },
+    };
+
+    let mut feed_values = Vec::new();
+    let mut values = Vec::new();
+
+    for field in fields {
+        let mut attributes = FieldAttributes::default();
+
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("iotics"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("label") {
+                    attributes.label = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("comment") {
+                    attributes.comment = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("unit") {
+                    attributes.unit = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("data_type") {
+                    attributes.data_type = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("skip") {
+                    attributes.skip = true;
+                } else {
+                    return Err(meta.error("unknown iotics feed value attribute"));
+                }
+
+                Ok(())
+            })?;
+        }
+
+        if attributes.skip {
+            continue;
+        }
+
+        let ident = field.ident.as_ref().expect("named fields have an ident");
+        let ty = &field.ty;
+        let label = attributes
+            .label
+            .map(|label| label.value())
+            .unwrap_or_else(|| ident.to_string().trim_start_matches("r#").to_string());
+        let comment = attributes
+            .comment
+            .map(|comment| comment.value())
+            .unwrap_or_default();
+        let unit = attributes.unit.map(|unit| unit.value()).unwrap_or_default();
+        let data_type = match attributes.data_type {
+            Some(data_type) => quote! { #data_type },
+            None => quote! { <#ty as ::iotics_connector_engine::feed::FeedField>::DATA_TYPE },
+        };
+
+        feed_values.push(quote! {
+            ::iotics_connector_engine::client::FeedValue {
+                label: #label.to_string(),
+                comment: #comment.to_string(),
+                data_type: #data_type.to_string(),
+                unit: #unit.to_string(),
+            }
+        });
+
+        values.push(quote! {
+            (#label, ::iotics_connector_engine::feed::FeedField::to_feed_value(&self.#ident))
+        });
+    }
+
+    Ok(quote! {
+        impl #impl_generics ::iotics_connector_engine::feed::IoticsFeed for #name #ty_generics #where_clause {
+            fn feed_id() -> &'static str {
+                #feed_id
+            }
+
+            fn feed() -> ::iotics_connector_engine::client::UpsertFeedWithMeta {
+                ::iotics_connector_engine::client::UpsertFeedWithMeta {
+                    id: #feed_id.to_string(),
+                    store_last: #store_last,
+                    values: vec![#(#feed_values),*],
+                    properties: #properties,
+                }
+            }
+
+            fn to_feed_value(&self) -> ::iotics_connector_engine::feed::SerdeValue {
+                ::iotics_connector_engine::feed::feed_value(vec![#(#values),*])
+            }
+        }
+    })
+}
+
+// a run of uppercase letters is a single word, e.g. `HTTPStatus` becomes `http_status`
+fn to_snake_case(name: &str) -> String {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut snake_case = String::new();
+
+    for (index, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && index > 0 {
+            let previous = chars[index - 1];
+            let next_is_lowercase = chars.get(index + 1).is_some_and(|next| next.is_lowercase());
+
+            if previous.is_lowercase()
+                || previous.is_numeric()
+                || (previous.is_uppercase() && next_is_lowercase)
+            {
+                snake_case.push('_');
+            }
+        }
+
+        snake_case.extend(c.to_lowercase());
+    }
+
+    snake_case
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snake_case() {
+        assert_eq!(to_snake_case("Weather"), "weather");
+        assert_eq!(to_snake_case("WeatherStation"), "weather_station");
+        assert_eq!(to_snake_case("weatherStation"), "weather_station");
+        assert_eq!(to_snake_case("HTTPStatus"), "http_status");
+        assert_eq!(to_snake_case("AirQualityPM25"), "air_quality_pm25");
+        assert_eq!(to_snake_case("Sensor2Reading"), "sensor2_reading");
+        assert_eq!(to_snake_case("IO"), "io");
+        assert_eq!(to_snake_case("already_snake"), "already_snake");
+    }
+}
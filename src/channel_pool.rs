@@ -0,0 +1,109 @@
+use anyhow::Context;
+use std::sync::Arc;
+
+use iotics_grpc_client::{create_channel, Channel};
+use tokio::sync::Mutex;
+
+use crate::config::AuthBuilder;
+use crate::retry::retry_with_backoff;
+use crate::settings::BackoffSettings;
+
+#[derive(Debug, Clone)]
+pub struct Channels {
+    pub twin_channel: Channel,
+    pub feed_channel: Channel,
+    /// Incremented every time the channels are recreated
+    pub generation: u64,
+}
+
+/// Twin and feed channels shared by the model actors of a process
+#[derive(Debug)]
+pub struct ChannelPool {
+    auth_builder: Arc<AuthBuilder>,
+    channels: Mutex<Option<Channels>>,
+}
+
+impl ChannelPool {
+    pub fn new(auth_builder: Arc<AuthBuilder>) -> Arc<Self> {
+        Arc::new(Self {
+            auth_builder,
+            channels: Mutex::new(None),
+        })
+    }
+
+    /// Returns the current channels, creating them first if needed
+    pub async fn get(
+        &self,
+        backoff: &BackoffSettings,
+        description: &str,
+    ) -> Result<Channels, anyhow::Error> {
+        if let Some(channels) = self.channels.lock().await.as_ref() {
+            return Ok(channels.clone());
+        }
+
+        let created = self.create(backoff, description, 1).await?;
+
+        Ok(self.store(created).await)
+    }
+
+    /// Replaces the channels of the given generation which were detected as broken.
+    /// If another model actor already replaced them, the current channels are returned.
+    pub async fn recreate(
+        &self,
+        broken_generation: u64,
+        backoff: &BackoffSettings,
+        description: &str,
+    ) -> Result<Channels, anyhow::Error> {
+        let generation = match self.channels.lock().await.as_ref() {
+            Some(current) if current.generation != broken_generation => {
+                return Ok(current.clone());
+            }
+            Some(current) => current.generation + 1,
+            None => 1,
+        };
+
+        let created = self.create(backoff, description, generation).await?;
+
+        Ok(self.store(created).await)
+    }
+
+    /// Stores the created channels unless another model actor stored the same or a newer
+    /// generation while they were being created, in which case those are returned.
+    /// The lock isn't held during the creation, which retries with backoff.
+    async fn store(&self, created: Channels) -> Channels {
+        let mut channels = self.channels.lock().await;
+
+        match channels.as_ref() {
+            Some(current) if current.generation >= created.generation => current.clone(),
+            _ => {
+                channels.replace(created.clone());
+                created
+            }
+        }
+    }
+
+    async fn create(
+        &self,
+        backoff: &BackoffSettings,
+        description: &str,
+        generation: u64,
+    ) -> Result<Channels, anyhow::Error> {
+        let auth_builder = &self.auth_builder;
+
+        retry_with_backoff(backoff, description, || async {
+            let twin_channel = create_channel(auth_builder.clone(), None, None, None)
+                .await
+                .context("failed to create the twin channel")?;
+            let feed_channel = create_channel(auth_builder.clone(), None, None, None)
+                .await
+                .context("failed to create the feed channel")?;
+
+            Ok::<Channels, anyhow::Error>(Channels {
+                twin_channel,
+                feed_channel,
+                generation,
+            })
+        })
+        .await
+    }
+}
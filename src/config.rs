@@ -1,26 +1,41 @@
+use std::fmt;
+use std::future::Future;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use iotics_grpc_client::IntoAuthBuilder;
 use iotics_identity::{create_agent_auth_token, Config};
+use log::{debug, warn};
 
-use crate::constants::AGENT_KEY_NAME;
+use crate::constants::{AGENT_KEY_NAME, TOKEN_RENEWAL_MARGIN};
+use crate::retry::grpc_status;
+
+#[derive(Debug, Clone)]
+struct Token {
+    value: String,
+    issued_at: Instant,
+}
 
 #[derive(Debug, Clone)]
 pub struct AuthBuilder {
     api_config: Arc<Mutex<ApiConfig>>,
-    token: Arc<Mutex<Option<String>>>,
+    token: Arc<Mutex<Option<Token>>>,
 }
 
 impl AuthBuilder {
-    pub fn new() -> Arc<Self> {
-        let api_config = get_api_config();
-
+    pub fn from_config(config: EngineConfig) -> Arc<Self> {
         Arc::new(Self {
-            api_config: Arc::new(Mutex::new(api_config)),
+            api_config: Arc::new(Mutex::new(ApiConfig::from(config))),
             token: Arc::new(Mutex::new(None)),
         })
     }
 
+    /// Builds the `AuthBuilder` from the `IOTICS_*` environment variables (and `.env` file)
+    pub fn from_env() -> Result<Arc<Self>, ConfigError> {
+        Ok(Self::from_config(EngineConfig::from_env()?))
+    }
+
     pub fn get_identity_config(&self) -> Result<Config, anyhow::Error> {
         let api_config_lock = self
             .api_config
@@ -29,6 +44,38 @@ impl AuthBuilder {
 
         Ok(api_config_lock.identity_config.clone())
     }
+
+    /// Drops the cached token so the next `get_token` call generates a new one
+    pub fn invalidate_token(&self) -> Result<(), anyhow::Error> {
+        let mut token_lock = self
+            .token
+            .lock()
+            .map_err(|_| anyhow::anyhow!("failed to lock the token mutex"))?;
+
+        token_lock.take();
+
+        Ok(())
+    }
+
+    /// Runs the gRPC call returned by `f` and, if the host rejected it because of the token,
+    /// regenerates the token and runs the call once more
+    pub async fn retry_on_auth_error<T, F, Fut>(&self, f: F) -> Result<T, anyhow::Error>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, anyhow::Error>>,
+    {
+        match f().await {
+            Err(e) if is_auth_error(&e) => {
+                warn!(
+                    "the host rejected the token, retrying with a new one: {:?}",
+                    e
+                );
+                self.invalidate_token()?;
+                f().await
+            }
+            result => result,
+        }
+    }
 }
 
 impl IntoAuthBuilder for AuthBuilder {
@@ -47,12 +94,18 @@ impl IntoAuthBuilder for AuthBuilder {
             .lock()
             .map_err(|_| anyhow::anyhow!("failed to lock the token mutex"))?;
 
-        if token_lock.is_none() {
-            let api_config_lock = self
-                .api_config
-                .lock()
-                .map_err(|_| anyhow::anyhow!("failed to lock the settings mutex"))?;
+        let api_config_lock = self
+            .api_config
+            .lock()
+            .map_err(|_| anyhow::anyhow!("failed to lock the settings mutex"))?;
 
+        let renew_after = api_config_lock.renew_token_after();
+        let expired = match token_lock.as_ref() {
+            Some(token) => token.issued_at.elapsed() >= renew_after,
+            None => true,
+        };
+
+        if expired {
             let identity_config = Config {
                 resolver_address: api_config_lock.identity_config.resolver_address.clone(),
                 token_duration: api_config_lock.identity_config.token_duration,
@@ -63,15 +116,240 @@ impl IntoAuthBuilder for AuthBuilder {
                 agent_secret: api_config_lock.identity_config.agent_secret.clone(),
             };
 
+            let issued_at = Instant::now();
             let token = create_agent_auth_token(&identity_config)?;
             let token = format!("bearer {token}");
 
-            token_lock.replace(token);
+            debug!("generated a new agent token");
+
+            token_lock.replace(Token {
+                value: token,
+                issued_at,
+            });
         }
 
         let token = token_lock.as_ref().expect("this should never happen");
 
-        Ok(token.clone())
+        Ok(token.value.clone())
+    }
+}
+
+/// Error returned when the engine configuration is incomplete or invalid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    Missing { key: String },
+    Invalid { key: String, reason: String },
+    File { path: PathBuf, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Missing { key } => write!(f, "config value {key} is missing"),
+            ConfigError::Invalid { key, reason } => {
+                write!(f, "config value {key} is invalid: {reason}")
+            }
+            ConfigError::File { path, reason } => {
+                write!(f, "failed to load config file {}: {reason}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Host and identity settings used to authenticate against an IOTICS host
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    host_address: String,
+    resolver_address: String,
+    user_did: String,
+    agent_did: String,
+    agent_name: String,
+    agent_secret: String,
+    token_duration: i64,
+    token_renewal_margin: Duration,
+}
+
+impl EngineConfig {
+    pub fn builder() -> EngineConfigBuilder {
+        EngineConfigBuilder::default()
+    }
+
+    /// Loads the config from the `IOTICS_*` environment variables (and `.env` file)
+    pub fn from_env() -> Result<Self, ConfigError> {
+        dotenv::dotenv().ok();
+
+        Self::builder().with_env_overrides()?.build_from_env()
+    }
+
+    pub fn host_address(&self) -> &str {
+        &self.host_address
+    }
+
+    pub fn resolver_address(&self) -> &str {
+        &self.resolver_address
+    }
+
+    pub fn user_did(&self) -> &str {
+        &self.user_did
+    }
+
+    pub fn agent_did(&self) -> &str {
+        &self.agent_did
+    }
+
+    pub fn agent_name(&self) -> &str {
+        &self.agent_name
+    }
+
+    pub fn token_duration(&self) -> i64 {
+        self.token_duration
+    }
+
+    pub fn token_renewal_margin(&self) -> Duration {
+        self.token_renewal_margin
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EngineConfigBuilder {
+    host_address: Option<String>,
+    resolver_address: Option<String>,
+    user_did: Option<String>,
+    agent_did: Option<String>,
+    agent_name: Option<String>,
+    agent_secret: Option<String>,
+    token_duration: Option<i64>,
+    token_renewal_margin: Option<Duration>,
+}
+
+impl EngineConfigBuilder {
+    pub fn host_address(mut self, host_address: impl Into<String>) -> Self {
+        self.host_address = Some(host_address.into());
+        self
+    }
+
+    pub fn resolver_address(mut self, resolver_address: impl Into<String>) -> Self {
+        self.resolver_address = Some(resolver_address.into());
+        self
+    }
+
+    pub fn user_did(mut self, user_did: impl Into<String>) -> Self {
+        self.user_did = Some(user_did.into());
+        self
+    }
+
+    pub fn agent_did(mut self, agent_did: impl Into<String>) -> Self {
+        self.agent_did = Some(agent_did.into());
+        self
+    }
+
+    /// The agent name, with or without the leading `#`
+    pub fn agent_name(mut self, agent_name: impl Into<String>) -> Self {
+        self.agent_name = Some(agent_name.into());
+        self
+    }
+
+    pub fn agent_secret(mut self, agent_secret: impl Into<String>) -> Self {
+        self.agent_secret = Some(agent_secret.into());
+        self
+    }
+
+    /// The token duration in seconds
+    pub fn token_duration(mut self, token_duration: i64) -> Self {
+        self.token_duration = Some(token_duration);
+        self
+    }
+
+    /// Regenerate the token this long before it expires.
+    /// Defaults to `TOKEN_RENEWAL_MARGIN`.
+    pub fn token_renewal_margin(mut self, token_renewal_margin: Duration) -> Self {
+        self.token_renewal_margin = Some(token_renewal_margin);
+        self
+    }
+
+    /// Overrides the values set so far with the `IOTICS_*` environment variables which are set
+    pub fn with_env_overrides(mut self) -> Result<Self, ConfigError> {
+        let env_string = |key: &str, value: &mut Option<String>| {
+            if let Some(env_value) = read_env(key) {
+                value.replace(env_value);
+            }
+        };
+
+        env_string("IOTICS_HOST_ADDRESS", &mut self.host_address);
+        env_string("IOTICS_RESOLVER_ADDRESS", &mut self.resolver_address);
+        env_string("IOTICS_USER_DID", &mut self.user_did);
+        env_string("IOTICS_AGENT_DID", &mut self.agent_did);
+        env_string("IOTICS_AGENT_NAME", &mut self.agent_name);
+        env_string("IOTICS_AGENT_SECRET", &mut self.agent_secret);
+
+        if let Some(duration) = read_env("IOTICS_TOKEN_DURATION") {
+            self.token_duration = Some(parse_value("IOTICS_TOKEN_DURATION", &duration)?);
+        }
+
+        if let Some(margin) = read_env("IOTICS_TOKEN_RENEWAL_MARGIN") {
+            self.token_renewal_margin = Some(Duration::from_secs(parse_value(
+                "IOTICS_TOKEN_RENEWAL_MARGIN",
+                &margin,
+            )?));
+        }
+
+        Ok(self)
+    }
+
+    /// Same as `build` but reports the keys as the environment variables they are read from
+    fn build_from_env(self) -> Result<EngineConfig, ConfigError> {
+        self.build().map_err(|e| match e {
+            ConfigError::Missing { key } => ConfigError::Missing { key: env_key(&key) },
+            ConfigError::Invalid { key, reason } => ConfigError::Invalid {
+                key: env_key(&key),
+                reason,
+            },
+            e => e,
+        })
+    }
+
+    pub fn build(self) -> Result<EngineConfig, ConfigError> {
+        let host_address = required("host_address", self.host_address)?;
+        let resolver_address = required("resolver_address", self.resolver_address)?;
+        let user_did = required("user_did", self.user_did)?;
+        let agent_did = required("agent_did", self.agent_did)?;
+        let agent_name = required("agent_name", self.agent_name)?;
+        let agent_secret = required("agent_secret", self.agent_secret)?;
+        let token_duration = self.token_duration.ok_or(ConfigError::Missing {
+            key: "token_duration".to_string(),
+        })?;
+
+        validate_did("user_did", &user_did)?;
+        validate_did("agent_did", &agent_did)?;
+
+        if token_duration <= 0 {
+            return Err(ConfigError::Invalid {
+                key: "token_duration".to_string(),
+                reason: "must be greater than 0".to_string(),
+            });
+        }
+
+        let agent_name = agent_name.trim_start_matches('#').to_string();
+
+        if agent_name.is_empty() {
+            return Err(ConfigError::Invalid {
+                key: "agent_name".to_string(),
+                reason: "must not be just '#'".to_string(),
+            });
+        }
+
+        Ok(EngineConfig {
+            host_address,
+            resolver_address,
+            user_did,
+            agent_did,
+            agent_name,
+            agent_secret,
+            token_duration,
+            token_renewal_margin: self.token_renewal_margin.unwrap_or(TOKEN_RENEWAL_MARGIN),
+        })
     }
 }
 
@@ -79,27 +357,175 @@ impl IntoAuthBuilder for AuthBuilder {
 struct ApiConfig {
     host_address: String,
     identity_config: Config,
+    token_renewal_margin: Duration,
+}
+
+impl From<EngineConfig> for ApiConfig {
+    fn from(config: EngineConfig) -> Self {
+        Self {
+            host_address: config.host_address,
+            identity_config: Config {
+                resolver_address: config.resolver_address,
+                user_did: config.user_did,
+                agent_did: config.agent_did,
+                agent_key_name: AGENT_KEY_NAME.to_string(),
+                agent_name: format!("#{}", config.agent_name),
+                agent_secret: config.agent_secret,
+                token_duration: config.token_duration,
+            },
+            token_renewal_margin: config.token_renewal_margin,
+        }
+    }
 }
 
-fn get_api_config() -> ApiConfig {
-    dotenv::dotenv().ok();
+impl ApiConfig {
+    /// How long a token can be used before it has to be regenerated.
+    /// If the margin doesn't fit in the token duration, the token is renewed at half its lifetime.
+    fn renew_token_after(&self) -> Duration {
+        let token_duration = Duration::from_secs(self.identity_config.token_duration.max(0) as u64);
 
-    let parse_env = |key: &str| -> String {
-        std::env::var(key).unwrap_or_else(|_| panic!("env var {key} is missing"))
+        token_duration
+            .checked_sub(self.token_renewal_margin)
+            .filter(|renew_after| !renew_after.is_zero())
+            .unwrap_or(token_duration / 2)
+    }
+}
+
+/// Checks whether the host rejected a call because of an invalid or expired token
+pub(crate) fn is_auth_error(error: &anyhow::Error) -> bool {
+    grpc_status(error).is_some_and(|status| status.code() == tonic::Code::Unauthenticated)
+}
+
+fn env_key(key: &str) -> String {
+    let env_key = match key {
+        "host_address" => "IOTICS_HOST_ADDRESS",
+        "resolver_address" => "IOTICS_RESOLVER_ADDRESS",
+        "user_did" => "IOTICS_USER_DID",
+        "agent_did" => "IOTICS_AGENT_DID",
+        "agent_name" => "IOTICS_AGENT_NAME",
+        "agent_secret" => "IOTICS_AGENT_SECRET",
+        "token_duration" => "IOTICS_TOKEN_DURATION",
+        "token_renewal_margin" => "IOTICS_TOKEN_RENEWAL_MARGIN",
+        _ => key,
     };
 
-    ApiConfig {
-        host_address: parse_env("IOTICS_HOST_ADDRESS"),
-        identity_config: Config {
-            resolver_address: parse_env("IOTICS_RESOLVER_ADDRESS"),
-            user_did: parse_env("IOTICS_USER_DID"),
-            agent_did: parse_env("IOTICS_AGENT_DID"),
-            agent_key_name: AGENT_KEY_NAME.to_string(),
-            agent_name: format!("#{}", parse_env("IOTICS_AGENT_NAME")),
-            agent_secret: parse_env("IOTICS_AGENT_SECRET"),
-            token_duration: parse_env("IOTICS_TOKEN_DURATION")
-                .parse::<i64>()
-                .expect("Failed to parse duration"),
-        },
+    env_key.to_string()
+}
+
+pub(crate) fn read_env(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|value| !value.is_empty())
+}
+
+pub(crate) fn parse_value<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, ConfigError>
+where
+    T::Err: fmt::Display,
+{
+    value.trim().parse::<T>().map_err(|e| ConfigError::Invalid {
+        key: key.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+fn required(key: &str, value: Option<String>) -> Result<String, ConfigError> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .ok_or(ConfigError::Missing {
+            key: key.to_string(),
+        })
+}
+
+fn validate_did(key: &str, did: &str) -> Result<(), ConfigError> {
+    if !did.starts_with("did:iotics:") {
+        return Err(ConfigError::Invalid {
+            key: key.to_string(),
+            reason: format!("{did} is not an IOTICS DID"),
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builder() -> EngineConfigBuilder {
+        EngineConfig::builder()
+            .host_address("https://example.iotics.space")
+            .resolver_address("https://did.prd.iotics.com")
+            .user_did("did:iotics:user")
+            .agent_did("did:iotics:agent")
+            .agent_name("#agent")
+            .agent_secret("secret")
+            .token_duration(60)
+    }
+
+    fn invalid_key(builder: EngineConfigBuilder) -> String {
+        match builder.build() {
+            Err(ConfigError::Invalid { key, .. }) => key,
+            result => panic!("expected the config to be invalid, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn builds_the_config() {
+        let config = builder().build().expect("the config should be valid");
+
+        assert_eq!(config.agent_name(), "agent");
+        assert_eq!(config.token_renewal_margin(), TOKEN_RENEWAL_MARGIN);
+    }
+
+    #[test]
+    fn requires_the_values() {
+        assert_eq!(
+            builder().agent_secret(" ").build().unwrap_err(),
+            ConfigError::Missing {
+                key: "agent_secret".to_string()
+            }
+        );
+        assert_eq!(
+            EngineConfigBuilder {
+                token_duration: None,
+                ..builder()
+            }
+            .build()
+            .unwrap_err(),
+            ConfigError::Missing {
+                key: "token_duration".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_the_invalid_values() {
+        assert_eq!(invalid_key(builder().user_did("user")), "user_did");
+        assert_eq!(
+            invalid_key(builder().agent_did("did:other:agent")),
+            "agent_did"
+        );
+        assert_eq!(invalid_key(builder().token_duration(0)), "token_duration");
+        assert_eq!(invalid_key(builder().agent_name("#")), "agent_name");
+    }
+
+    #[test]
+    fn renews_the_token_before_it_expires() {
+        let api_config = |token_renewal_margin| {
+            ApiConfig::from(
+                builder()
+                    .token_renewal_margin(token_renewal_margin)
+                    .build()
+                    .expect("the config should be valid"),
+            )
+        };
+
+        assert_eq!(
+            api_config(Duration::from_secs(10)).renew_token_after(),
+            Duration::from_secs(50)
+        );
+        assert_eq!(
+            api_config(Duration::from_secs(60)).renew_token_after(),
+            Duration::from_secs(30)
+        );
     }
 }
@@ -1,12 +1,43 @@
 use async_trait::async_trait;
+use futures::stream::BoxStream;
 use iotics_grpc_client::{GeoLocation, Property};
 use serde_json::Value as SerdeValue;
 use std::collections::HashMap;
 use std::fmt::Debug;
 
+use crate::feed::IoticsFeed;
+
+#[async_trait]
+pub trait Connector: Debug + Send + Sync {
+    /// Returns the data of the twins. An error fails the whole fetch, the twins whose data
+    /// couldn't be read are returned in `FetchResult::errors` instead.
+    async fn get_data(&self) -> Result<FetchResult, anyhow::Error>;
+
+    /// Called with the feed values which couldn't be shared, even after retrying
+    async fn on_share_failure(&self, _failure: ShareFailure) {}
+
+    /// Called with the values shared to the remote feeds listed in `ModelSettings::follow`
+    async fn on_followed_data(&self, _data: FollowedData) {}
+
+    /// Called with the DIDs of the twins of the model found on the host at startup which aren't
+    /// in the source anymore, when they are not deleted (`delete_twins` is false)
+    async fn on_orphan_twins(&self, _twin_dids: Vec<String>) {}
+
+    /// Called with the messages received by the inputs of the twins, see `Model::with_inputs`
+    async fn on_input(&self, _input: ReceivedInput) {}
+
+    /// Called with the feed values which don't match the feeds of the model, they are not shared.
+    /// The error is a `ValidationError`.
+    async fn on_invalid_value(&self, _failure: ShareFailure) {}
+}
+
+/// A connector for the sources which push their data (message brokers, websockets, change feeds...)
+/// instead of being polled. `get_data` is called once when the model starts to share the current
+/// state, return an empty `FetchResult` if the source has none.
 #[async_trait]
-pub trait Connector: Debug {
-    async fn get_data(&self) -> Result<Vec<ConnectorData>, anyhow::Error>;
+pub trait StreamingConnector: Connector {
+    /// Opens the stream of data. It is opened again if it ends.
+    async fn stream(&self) -> Result<BoxStream<'static, ConnectorData>, anyhow::Error>;
 }
 
 #[derive(Debug, Clone)]
@@ -18,6 +49,97 @@ pub struct ConnectorData {
     pub properties: Vec<Property>,
 }
 
+impl ConnectorData {
+    /// Adds the values of the feed, replacing the previous ones
+    pub fn with_feed<F: IoticsFeed>(mut self, feed: &F) -> Self {
+        self.feeds
+            .insert(F::feed_id().to_string(), feed.to_feed_value());
+        self
+    }
+}
+
+/// The result of `Connector::get_data`, `Ok(data.into())` when every twin was read
+#[derive(Debug, Default)]
+pub struct FetchResult {
+    pub data: Vec<ConnectorData>,
+    pub errors: Vec<TwinError>,
+}
+
+impl FetchResult {
+    pub fn new(data: Vec<ConnectorData>, errors: Vec<TwinError>) -> Self {
+        Self { data, errors }
+    }
+}
+
+impl From<Vec<ConnectorData>> for FetchResult {
+    fn from(data: Vec<ConnectorData>) -> Self {
+        Self {
+            data,
+            errors: Vec::new(),
+        }
+    }
+}
+
+/// A twin whose data couldn't be read from the source. It is logged, counted in the metrics
+/// and passed to the error handler, the twin is kept.
+#[derive(Debug)]
+pub struct TwinError {
+    /// `ConnectorData::id` of the twin
+    pub twin_id: String,
+    pub error: anyhow::Error,
+    /// Shared to the twin in place of its data, e.g. a status property or feed
+    pub status: Option<ConnectorData>,
+}
+
+impl TwinError {
+    pub fn new(twin_id: impl Into<String>, error: anyhow::Error) -> Self {
+        Self {
+            twin_id: twin_id.into(),
+            error,
+            status: None,
+        }
+    }
+
+    /// Shares the given data to the twin, its `id` is replaced with the `twin_id`
+    pub fn with_status(mut self, status: ConnectorData) -> Self {
+        self.status = Some(status);
+        self
+    }
+}
+
+#[derive(Debug)]
+pub struct ShareFailure {
+    /// `ConnectorData::id` of the twin
+    pub twin_id: String,
+    pub twin_did: String,
+    pub feed_id: String,
+    pub value: SerdeValue,
+    pub error: anyhow::Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReceivedInput {
+    /// `ConnectorData::id` of the twin
+    pub twin_id: String,
+    pub twin_did: String,
+    pub input_id: String,
+    /// The message data, parsed as JSON if possible or as a string otherwise
+    pub value: SerdeValue,
+}
+
+#[derive(Debug, Clone)]
+pub struct FollowedData {
+    pub twin_did: String,
+    pub feed_id: String,
+    /// The shared data, parsed as JSON if possible or as a string otherwise
+    pub value: SerdeValue,
+}
+
+pub(crate) fn parse_message_data(data: &[u8]) -> SerdeValue {
+    serde_json::from_slice(data)
+        .unwrap_or_else(|_| SerdeValue::String(String::from_utf8_lossy(data).into_owned()))
+}
+
 // Convert a String object into an f64 if "field" contains a number or return None otherwise
 pub fn parse_to_float(field: String) -> Option<f64> {
     if !field.is_empty() {
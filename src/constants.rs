@@ -9,5 +9,72 @@ pub const RESCHEDULE_DELAY: Duration = Duration::from_millis(500);
 // this should match the label max length - see PATTERN_LABEL in https://github.com/Iotic-Labs/iotic-lib-metadata
 pub const MAX_LABEL_LENGTH: usize = 128;
 pub const LANGUAGE: &str = "en";
+pub const DEFAULT_FETCH_EVERY_SECS: u64 = 60;
 // set the cleanup interval to be 3.5 bigger than the fetch interval
 pub const CLEANUP_INTERVAL_MULTIPLIER: f64 = 3.5;
+// regenerate the agent token this long before it expires
+pub const TOKEN_RENEWAL_MARGIN: Duration = Duration::from_secs(30);
+// default exponential backoff used when retrying calls to the host
+pub const BACKOFF_INITIAL_DELAY: Duration = Duration::from_secs(1);
+pub const BACKOFF_MAX_DELAY: Duration = Duration::from_secs(60);
+pub const BACKOFF_MULTIPLIER: f64 = 2.0;
+pub const BACKOFF_JITTER: f64 = 0.2;
+pub const BACKOFF_MAX_ATTEMPTS: u32 = 10;
+// recreate the gRPC channels after this many consecutive transport errors
+pub const CHANNEL_FAILURE_THRESHOLD: usize = 5;
+// feed shares are retried quickly, they keep a concurrent share slot while retrying
+pub const SHARE_BACKOFF_INITIAL_DELAY: Duration = Duration::from_millis(200);
+pub const SHARE_BACKOFF_MAX_DELAY: Duration = Duration::from_secs(5);
+pub const SHARE_MAX_ATTEMPTS: u32 = 3;
+// the twin creation keeps a concurrent new twin slot while retrying
+pub const TWIN_CREATION_BACKOFF_MAX_DELAY: Duration = Duration::from_secs(10);
+pub const TWIN_CREATION_MAX_ATTEMPTS: u32 = 5;
+pub const QUARANTINE_DURATION: Duration = Duration::from_secs(3600);
+// how long the twins are given to finish their in-flight shares when shutting down
+pub const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(30);
+pub const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+// how long the startup reconciliation waits for the host search results
+pub const SEARCH_TIMEOUT: Duration = Duration::from_secs(10);
+// the requests to the engine HTTP server are only a request line and a few headers
+pub const HTTP_REQUEST_MAX_SIZE: usize = 8192;
+// the status of a model actor too busy to answer in time is read from its metrics
+pub const MODEL_STATUS_TIMEOUT: Duration = Duration::from_secs(1);
+// the connections which don't send their request in time are closed
+pub const HTTP_READ_TIMEOUT: Duration = Duration::from_secs(5);
+// the connections above it wait to be accepted
+pub const HTTP_MAX_CONNECTIONS: usize = 16;
+// the accept errors, like running out of file descriptors, are usually transient
+pub const HTTP_ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+// the model is not live when nothing was fetched or shared for this many fetch intervals
+pub const LIVENESS_MULTIPLIER: f64 = 5.0;
+// how many times the engine restarts a model actor which stopped unexpectedly
+pub const MAX_MODEL_RESTARTS: u32 = 5;
+
+// unix timestamps above this are in milliseconds, it is year 5138 in seconds
+pub const MAX_TIMESTAMP_SECS: i64 = 100_000_000_000;
+// the FeedValue data types supported by IOTICS
+pub const XSD_DATA_TYPES: &[&str] = &[
+    "anyURI",
+    "boolean",
+    "byte",
+    "date",
+    "dateTime",
+    "decimal",
+    "double",
+    "duration",
+    "float",
+    "int",
+    "integer",
+    "long",
+    "negativeInteger",
+    "nonNegativeInteger",
+    "nonPositiveInteger",
+    "positiveInteger",
+    "short",
+    "string",
+    "time",
+    "unsignedByte",
+    "unsignedInt",
+    "unsignedLong",
+    "unsignedShort",
+];
@@ -0,0 +1,481 @@
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use actix::clock::timeout;
+use actix::{
+    Actor, Addr, AsyncContext, Context, Handler, MailboxError, MessageResponse, ResponseFuture,
+    System,
+};
+use log::{error, info, warn};
+use tokio::sync::oneshot;
+
+use crate::channel_pool::ChannelPool;
+use crate::config::AuthBuilder;
+use crate::connector::{Connector, StreamingConnector};
+use crate::constants::{MAX_MODEL_RESTARTS, MODEL_STATUS_TIMEOUT, SHUTDOWN_DEADLINE};
+use crate::error::{EngineError, ErrorHandler};
+use crate::http;
+use crate::messages::{GetEngineStatus, GetStatus, ModelStopped, Shutdown};
+use crate::metrics::{Metrics, ModelMetrics};
+use crate::model::Model;
+use crate::model_actor::ModelActor;
+use crate::settings::ModelSettings;
+use crate::shutdown::wait_for_signal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelState {
+    /// Creating the channels and the model twin
+    Starting,
+    Running,
+    RecreatingChannels,
+    ShuttingDown,
+    /// Stopped after running out of restarts
+    Failed,
+}
+
+#[derive(Debug, Clone, MessageResponse)]
+pub struct ModelStatus {
+    pub label: String,
+    pub model_did: Option<String>,
+    pub state: ModelState,
+    pub running_twins: usize,
+    pub quarantined_twins: usize,
+    pub concurrent_new_twins: usize,
+    pub concurrent_shares: usize,
+    /// How many times the model actor was restarted by the engine
+    pub restarts: u32,
+    /// When data was last fetched or shared to a twin feed
+    pub last_activity: Option<SystemTime>,
+    /// Whether data was fetched or shared within `liveness_multiplier` fetch intervals
+    pub live: bool,
+}
+
+impl ModelStatus {
+    /// The channels are created and the model twin is upserted
+    pub fn is_ready(&self) -> bool {
+        self.state == ModelState::Running
+    }
+}
+
+#[derive(Debug, Clone, MessageResponse)]
+pub struct EngineStatus {
+    pub models: Vec<ModelStatus>,
+}
+
+impl EngineStatus {
+    /// Every model is running
+    pub fn is_ready(&self) -> bool {
+        self.models.iter().all(ModelStatus::is_ready)
+    }
+
+    /// No model failed for good or stopped fetching and sharing
+    pub fn is_live(&self) -> bool {
+        self.models
+            .iter()
+            .all(|model| model.state != ModelState::Failed && model.live)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ModelRegistration {
+    model: Model,
+    connector: Arc<dyn Connector>,
+    streamer: Option<Arc<dyn StreamingConnector>>,
+    settings: ModelSettings,
+}
+
+/// Hosts several models in one process, sharing the `AuthBuilder` and the gRPC channels.
+/// The model actors which stop unexpectedly are restarted, up to `max_restarts` times.
+///
+/// ```ignore
+/// Engine::new(AuthBuilder::from_env()?)
+///     .register(weather_model, weather_connector, ModelSettings::new(60, true))
+///     .register(traffic_model, traffic_connector, ModelSettings::new(30, true))
+///     .run()
+///     .await?;
+/// ```
+#[derive(Debug)]
+pub struct Engine {
+    auth_builder: Arc<AuthBuilder>,
+    registrations: Vec<ModelRegistration>,
+    max_restarts: u32,
+    shutdown_deadline: Duration,
+    http_addr: Option<SocketAddr>,
+    error_handler: ErrorHandler,
+}
+
+impl Engine {
+    pub fn new(auth_builder: Arc<AuthBuilder>) -> Self {
+        Self {
+            auth_builder,
+            registrations: Vec::new(),
+            max_restarts: MAX_MODEL_RESTARTS,
+            shutdown_deadline: SHUTDOWN_DEADLINE,
+            http_addr: None,
+            error_handler: ErrorHandler::default(),
+        }
+    }
+
+    pub fn register(
+        mut self,
+        model: Model,
+        connector: Arc<dyn Connector>,
+        settings: ModelSettings,
+    ) -> Self {
+        self.registrations.push(ModelRegistration {
+            model,
+            connector,
+            streamer: None,
+            settings,
+        });
+        self
+    }
+
+    /// Registers a model whose data is pushed by the connector stream instead of being polled
+    pub fn register_streaming<C: StreamingConnector + 'static>(
+        mut self,
+        model: Model,
+        connector: Arc<C>,
+        settings: ModelSettings,
+    ) -> Self {
+        self.registrations.push(ModelRegistration {
+            model,
+            connector: connector.clone(),
+            streamer: Some(connector),
+            settings,
+        });
+        self
+    }
+
+    /// How many times a model actor is restarted before the engine gives up
+    pub fn with_max_restarts(mut self, max_restarts: u32) -> Self {
+        self.max_restarts = max_restarts;
+        self
+    }
+
+    /// How long the twins are given to finish their in-flight shares when shutting down
+    pub fn with_shutdown_deadline(mut self, shutdown_deadline: Duration) -> Self {
+        self.shutdown_deadline = shutdown_deadline;
+        self
+    }
+
+    /// Serves the Prometheus metrics of the models on `http://<addr>/metrics`,
+    /// and the liveness and readiness checks on `/healthz` and `/readyz`
+    pub fn with_http_addr(mut self, http_addr: SocketAddr) -> Self {
+        self.http_addr = Some(http_addr);
+        self
+    }
+
+    /// Calls `callback` with the errors of the models, e.g. a failed fetch or a full mailbox.
+    /// They are logged either way.
+    pub fn with_error_handler(
+        mut self,
+        callback: impl Fn(&EngineError) + Send + Sync + 'static,
+    ) -> Self {
+        self.error_handler = ErrorHandler::new(callback);
+        self
+    }
+
+    /// Starts the models. Must be called from within an actix `System`.
+    pub fn start(self) -> EngineHandle {
+        let (failure_sender, failure_receiver) = oneshot::channel();
+        let channel_pool = ChannelPool::new(self.auth_builder.clone());
+        let metrics = Arc::new(Metrics::default());
+
+        let addr = EngineActor {
+            auth_builder: self.auth_builder,
+            channel_pool,
+            models: self
+                .registrations
+                .into_iter()
+                .map(|registration| SupervisedModel {
+                    metrics: metrics.model(
+                        &registration.model.get_seed(),
+                        &registration.model.get_label(),
+                    ),
+                    registration,
+                    addr: None,
+                    restarts: 0,
+                    failed: false,
+                })
+                .collect(),
+            max_restarts: self.max_restarts,
+            failure_sender: Some(failure_sender),
+            error_handler: self.error_handler,
+            shutting_down: false,
+        }
+        .start();
+
+        if let Some(http_addr) = self.http_addr {
+            actix::spawn(http::run(http_addr, metrics));
+        }
+
+        EngineHandle {
+            addr,
+            shutdown_deadline: self.shutdown_deadline,
+            failure_receiver: Some(failure_receiver),
+        }
+    }
+
+    /// Starts the models and runs until SIGTERM/SIGINT, then shuts down cleanly.
+    /// Returns an error if a model failed for good.
+    pub async fn run(self) -> Result<(), anyhow::Error> {
+        self.start().run_until_signal().await
+    }
+}
+
+#[derive(Debug)]
+pub struct EngineHandle {
+    addr: Addr<EngineActor>,
+    shutdown_deadline: Duration,
+    failure_receiver: Option<oneshot::Receiver<anyhow::Error>>,
+}
+
+impl EngineHandle {
+    pub async fn status(&self) -> Result<EngineStatus, MailboxError> {
+        self.addr.send(GetEngineStatus).await
+    }
+
+    /// Stops fetching, lets the twins finish their in-flight shares and shares a final heartbeat
+    pub async fn shutdown(&self) -> Result<(), MailboxError> {
+        self.addr
+            .send(Shutdown {
+                deadline: self.shutdown_deadline,
+            })
+            .await
+    }
+
+    /// Waits for SIGTERM/SIGINT or for a model to fail for good, then shuts down
+    pub async fn run_until_signal(mut self) -> Result<(), anyhow::Error> {
+        let failure_receiver = self
+            .failure_receiver
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("the engine is already running"))?;
+
+        let result = tokio::select! {
+            signal = wait_for_signal() => {
+                match signal {
+                    Ok(signal) => info!("Received {}, shutting down", signal),
+                    Err(e) => error!("failed to listen for the shutdown signals {:?}", e),
+                }
+
+                Ok(())
+            }
+            Ok(error) = failure_receiver => Err(error),
+        };
+
+        self.shutdown().await?;
+
+        #[cfg(feature = "otlp")]
+        crate::trace::shutdown_otlp();
+
+        result
+    }
+}
+
+#[derive(Debug)]
+struct SupervisedModel {
+    registration: ModelRegistration,
+    /// Kept across the restarts of the model actor
+    metrics: Arc<ModelMetrics>,
+    addr: Option<Addr<ModelActor>>,
+    restarts: u32,
+    failed: bool,
+}
+
+#[derive(Debug)]
+pub(crate) struct EngineActor {
+    auth_builder: Arc<AuthBuilder>,
+    channel_pool: Arc<ChannelPool>,
+    models: Vec<SupervisedModel>,
+    max_restarts: u32,
+    failure_sender: Option<oneshot::Sender<anyhow::Error>>,
+    error_handler: ErrorHandler,
+    shutting_down: bool,
+}
+
+impl EngineActor {
+    fn start_model(&mut self, index: usize, ctx: &mut Context<Self>) {
+        let auth_builder = self.auth_builder.clone();
+        let channel_pool = self.channel_pool.clone();
+        let error_handler = self.error_handler.clone();
+        let supervised = &mut self.models[index];
+        let registration = supervised.registration.clone();
+        let metrics = supervised.metrics.clone();
+
+        let mut model_actor = ModelActor::new(
+            auth_builder,
+            registration.model,
+            registration.connector,
+            registration.settings,
+        )
+        .with_channel_pool(channel_pool)
+        .with_metrics(metrics)
+        .with_error_handler(error_handler)
+        .with_supervisor(ctx.address().recipient());
+
+        if let Some(streamer) = registration.streamer {
+            model_actor = model_actor.with_streamer(streamer);
+        }
+
+        let addr = model_actor.start();
+
+        supervised.addr.replace(addr);
+    }
+}
+
+impl Actor for EngineActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!("Engine started with {} models", self.models.len());
+
+        for index in 0..self.models.len() {
+            self.start_model(index, ctx);
+        }
+    }
+}
+
+impl Handler<ModelStopped> for EngineActor {
+    type Result = ();
+
+    fn handle(&mut self, message: ModelStopped, ctx: &mut Context<Self>) -> Self::Result {
+        let index =
+            match self.models.iter().position(|supervised| {
+                supervised.registration.model.get_seed() == message.model_seed
+            }) {
+                Some(index) => index,
+                None => return,
+            };
+
+        let max_restarts = self.max_restarts;
+        let supervised = &mut self.models[index];
+        let model_label = supervised.registration.model.get_label();
+        supervised.addr = None;
+
+        if self.shutting_down {
+            return;
+        }
+
+        if supervised.restarts >= max_restarts {
+            self.error_handler.report(EngineError::ModelFailed {
+                model: model_label.clone(),
+                restarts: supervised.restarts,
+            });
+            supervised.failed = true;
+            supervised.metrics.set_state(ModelState::Failed);
+
+            if let Some(failure_sender) = self.failure_sender.take() {
+                let _ = failure_sender.send(anyhow::anyhow!(
+                    "[{model_label}] model stopped after {max_restarts} restarts"
+                ));
+            } else {
+                System::current().stop_with_code(1);
+            }
+
+            return;
+        }
+
+        supervised.restarts += 1;
+        supervised.metrics.set_state(ModelState::Starting);
+        let delay = supervised
+            .registration
+            .settings
+            .startup_backoff
+            .delay(supervised.restarts);
+
+        warn!(
+            "[{}] model stopped, restarting it in {:?} (restart {}/{})",
+            &model_label, delay, supervised.restarts, max_restarts
+        );
+
+        ctx.run_later(delay, move |act, ctx| {
+            // the engine may have started shutting down in the meantime
+            if !act.shutting_down {
+                act.start_model(index, ctx);
+            }
+        });
+    }
+}
+
+impl Handler<GetEngineStatus> for EngineActor {
+    type Result = ResponseFuture<EngineStatus>;
+
+    fn handle(&mut self, _: GetEngineStatus, _: &mut Context<Self>) -> Self::Result {
+        let models: Vec<_> = self
+            .models
+            .iter()
+            .map(|supervised| {
+                (
+                    supervised.addr.clone(),
+                    supervised.registration.model.get_label(),
+                    supervised.metrics.clone(),
+                    supervised.restarts,
+                    supervised.failed,
+                )
+            })
+            .collect();
+
+        Box::pin(async move {
+            let mut statuses = Vec::new();
+
+            for (addr, label, metrics, restarts, failed) in models {
+                let status = match addr {
+                    Some(addr) if !failed => timeout(MODEL_STATUS_TIMEOUT, addr.send(GetStatus))
+                        .await
+                        .ok()
+                        .and_then(Result::ok),
+                    _ => None,
+                };
+
+                // the model actor is restarting or too busy to answer
+                let status = match status {
+                    Some(status) => ModelStatus { restarts, ..status },
+                    None => ModelStatus {
+                        label,
+                        model_did: None,
+                        state: metrics.state(),
+                        running_twins: metrics.twins_running.load(Ordering::Relaxed),
+                        quarantined_twins: 0,
+                        concurrent_new_twins: metrics.concurrent_new_twins.load(Ordering::Relaxed),
+                        concurrent_shares: metrics.concurrent_shares.load(Ordering::Relaxed),
+                        restarts,
+                        last_activity: metrics.last_activity(),
+                        live: metrics.is_live(),
+                    },
+                };
+
+                statuses.push(status);
+            }
+
+            EngineStatus { models: statuses }
+        })
+    }
+}
+
+impl Handler<Shutdown> for EngineActor {
+    type Result = ResponseFuture<()>;
+
+    fn handle(&mut self, message: Shutdown, _: &mut Context<Self>) -> Self::Result {
+        info!("Engine shutting down");
+        self.shutting_down = true;
+
+        // the messages are queued straight away so the models shut down concurrently
+        let requests: Vec<_> = self
+            .models
+            .iter()
+            .filter_map(|supervised| supervised.addr.as_ref())
+            .map(|addr| addr.send(message.clone()))
+            .collect();
+
+        Box::pin(async move {
+            for request in requests {
+                if let Err(e) = request.await {
+                    error!("failed to shut down a model {:?}", e);
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,118 @@
+use std::fmt;
+use std::sync::Arc;
+
+use log::error;
+
+/// Errors of the engine which don't stop it straight away, passed to the `ErrorHandler`
+#[derive(Debug)]
+pub enum EngineError {
+    /// The mailbox of an actor was full and the message was dropped
+    MailboxFull {
+        model: String,
+        message: &'static str,
+    },
+    /// The actor stopped before the message could be delivered
+    MailboxClosed {
+        model: String,
+        message: &'static str,
+    },
+    /// The identity settings couldn't be read
+    Identity { model: String, error: anyhow::Error },
+    /// The model twin couldn't be created, even after retrying. The model actor stops.
+    ModelCreation { model: String, error: anyhow::Error },
+    /// `Connector::get_data` returned an error
+    Fetch { model: String, error: anyhow::Error },
+    /// `Connector::get_data` couldn't read the data of a twin, see `FetchResult::errors`
+    TwinData {
+        model: String,
+        twin_id: String,
+        error: anyhow::Error,
+    },
+    /// The stream of a `StreamingConnector` couldn't be reopened. The model actor stops.
+    DataStream { model: String, error: anyhow::Error },
+    /// The model actor stopped unexpectedly too many times, the engine gave up restarting it
+    ModelFailed { model: String, restarts: u32 },
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::MailboxFull { model, message } => {
+                write!(f, "[{model}] mailbox full, dropped {message}")
+            }
+            EngineError::MailboxClosed { model, message } => {
+                write!(f, "[{model}] actor stopped, dropped {message}")
+            }
+            EngineError::Identity { model, error } => {
+                write!(f, "[{model}] failed to get the identity config: {error:?}")
+            }
+            EngineError::ModelCreation { model, error } => {
+                write!(f, "[{model}] giving up on creating the model: {error:?}")
+            }
+            EngineError::Fetch { model, error } => {
+                write!(f, "[{model}] failed to receive data: {error:?}")
+            }
+            EngineError::TwinData {
+                model,
+                twin_id,
+                error,
+            } => {
+                write!(
+                    f,
+                    "[{model}] failed to receive the data of twin {twin_id}: {error:?}"
+                )
+            }
+            EngineError::DataStream { model, error } => {
+                write!(f, "[{model}] giving up on the data stream: {error:?}")
+            }
+            EngineError::ModelFailed { model, restarts } => {
+                write!(f, "[{model}] model stopped after {restarts} restarts")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EngineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EngineError::Identity { error, .. }
+            | EngineError::ModelCreation { error, .. }
+            | EngineError::Fetch { error, .. }
+            | EngineError::TwinData { error, .. }
+            | EngineError::DataStream { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+type ErrorCallback = Arc<dyn Fn(&EngineError) + Send + Sync>;
+
+/// Logs the engine errors and passes them to the callback set with `Engine::with_error_handler`
+#[derive(Clone, Default)]
+pub struct ErrorHandler {
+    callback: Option<ErrorCallback>,
+}
+
+impl ErrorHandler {
+    pub fn new(callback: impl Fn(&EngineError) + Send + Sync + 'static) -> Self {
+        Self {
+            callback: Some(Arc::new(callback)),
+        }
+    }
+
+    pub fn report(&self, error: EngineError) {
+        error!("{}", error);
+
+        if let Some(callback) = self.callback.as_ref() {
+            callback(&error);
+        }
+    }
+}
+
+impl fmt::Debug for ErrorHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorHandler")
+            .field("callback", &self.callback.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,125 @@
+use iotics_grpc_client::twin::UpsertFeedWithMeta;
+use serde_json::{Map, Number};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+pub use serde_json::Value as SerdeValue;
+
+#[cfg(feature = "derive")]
+pub use iotics_connector_engine_derive::IoticsFeed;
+
+/// A feed whose declaration and values come from the same struct so they can't drift apart.
+/// Usually implemented with `#[derive(IoticsFeed)]`, behind the `derive` feature.
+///
+/// ```ignore
+/// let model = Model::new(seed_prefix, label_prefix, model_properties, vec![Weather::feed()], twin_properties);
+///
+/// let data = ConnectorData { .. }.with_feed(&weather);
+/// ```
+pub trait IoticsFeed {
+    fn feed_id() -> &'static str;
+
+    /// The feed declaration, with a `FeedValue` per field
+    fn feed() -> UpsertFeedWithMeta;
+
+    /// The values shared to the feed, keyed by the `FeedValue` labels
+    fn to_feed_value(&self) -> SerdeValue;
+}
+
+/// The types which can be a field of an `IoticsFeed`
+pub trait FeedField {
+    /// XML schema data type of the `FeedValue`
+    const DATA_TYPE: &'static str;
+
+    /// Null if there is no value to share
+    fn to_feed_value(&self) -> SerdeValue;
+}
+
+macro_rules! impl_integer_feed_field {
+    ($($ty:ty),*) => {
+        $(
+            impl FeedField for $ty {
+                const DATA_TYPE: &'static str = "integer";
+
+                fn to_feed_value(&self) -> SerdeValue {
+                    SerdeValue::from(*self)
+                }
+            }
+        )*
+    };
+}
+
+impl_integer_feed_field!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl FeedField for f32 {
+    const DATA_TYPE: &'static str = "decimal";
+
+    fn to_feed_value(&self) -> SerdeValue {
+        (*self as f64).to_feed_value()
+    }
+}
+
+impl FeedField for f64 {
+    const DATA_TYPE: &'static str = "decimal";
+
+    fn to_feed_value(&self) -> SerdeValue {
+        Number::from_f64(*self)
+            .map(SerdeValue::Number)
+            .unwrap_or(SerdeValue::Null)
+    }
+}
+
+impl FeedField for bool {
+    const DATA_TYPE: &'static str = "boolean";
+
+    fn to_feed_value(&self) -> SerdeValue {
+        SerdeValue::Bool(*self)
+    }
+}
+
+impl FeedField for String {
+    const DATA_TYPE: &'static str = "string";
+
+    fn to_feed_value(&self) -> SerdeValue {
+        SerdeValue::String(self.clone())
+    }
+}
+
+impl FeedField for &str {
+    const DATA_TYPE: &'static str = "string";
+
+    fn to_feed_value(&self) -> SerdeValue {
+        SerdeValue::String(self.to_string())
+    }
+}
+
+impl FeedField for OffsetDateTime {
+    const DATA_TYPE: &'static str = "dateTime";
+
+    fn to_feed_value(&self) -> SerdeValue {
+        self.format(&Rfc3339)
+            .map(SerdeValue::String)
+            .unwrap_or(SerdeValue::Null)
+    }
+}
+
+impl<T: FeedField> FeedField for Option<T> {
+    const DATA_TYPE: &'static str = T::DATA_TYPE;
+
+    fn to_feed_value(&self) -> SerdeValue {
+        match self {
+            Some(value) => value.to_feed_value(),
+            None => SerdeValue::Null,
+        }
+    }
+}
+
+/// Builds the value of a feed from its labelled values, leaving the null ones out
+pub fn feed_value(values: Vec<(&str, SerdeValue)>) -> SerdeValue {
+    let values: Map<String, SerdeValue> = values
+        .into_iter()
+        .filter(|(_, value)| !value.is_null())
+        .map(|(label, value)| (label.to_string(), value))
+        .collect();
+
+    SerdeValue::Object(values)
+}
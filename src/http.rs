@@ -0,0 +1,151 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use actix::clock::{sleep, timeout};
+use log::{debug, error, info};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+
+use crate::constants::{
+    HTTP_ACCEPT_RETRY_DELAY, HTTP_MAX_CONNECTIONS, HTTP_READ_TIMEOUT, HTTP_REQUEST_MAX_SIZE,
+};
+use crate::engine::ModelState;
+use crate::metrics::{Metrics, ModelMetrics};
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn text(status: &'static str, body: String) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body,
+        }
+    }
+}
+
+/// Serves the engine metrics on `/metrics` and the liveness and readiness checks on `/healthz`
+/// and `/readyz`. A bare HTTP/1.1 server is enough for the scrapers and probes,
+/// every connection handles a single GET request. At most `HTTP_MAX_CONNECTIONS` are handled
+/// at once and the request must be received within `HTTP_READ_TIMEOUT`. A failed accept is
+/// logged and retried after `HTTP_ACCEPT_RETRY_DELAY`, it doesn't stop the server.
+/// The checks read the state the models publish in their metrics, they don't wait on the actors.
+pub(crate) async fn serve(addr: SocketAddr, metrics: Arc<Metrics>) -> Result<(), anyhow::Error> {
+    let listener = TcpListener::bind(addr).await?;
+    let connections = Arc::new(Semaphore::new(HTTP_MAX_CONNECTIONS));
+    info!("Serving the metrics and health checks on http://{}", addr);
+
+    loop {
+        let permit = connections.clone().acquire_owned().await?;
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                error!("failed to accept an HTTP connection {:?}", e);
+                drop(permit);
+                sleep(HTTP_ACCEPT_RETRY_DELAY).await;
+                continue;
+            }
+        };
+        let metrics = metrics.clone();
+
+        actix::spawn(async move {
+            if let Err(e) = handle_connection(stream, &metrics).await {
+                debug!("failed to answer the HTTP request of {} {:?}", peer, e);
+            }
+
+            drop(permit);
+        });
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, metrics: &Metrics) -> Result<(), anyhow::Error> {
+    let request = timeout(HTTP_READ_TIMEOUT, read_request(&mut stream))
+        .await
+        .map_err(|_| anyhow::anyhow!("request not received in {:?}", HTTP_READ_TIMEOUT))??;
+
+    let mut request_line = request
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line.next().unwrap_or_default();
+
+    let response = match (method, path) {
+        ("GET", "/metrics") => Response {
+            status: "200 OK",
+            content_type: "text/plain; version=0.0.4; charset=utf-8",
+            body: metrics.render(),
+        },
+        ("GET", "/healthz") => check(metrics, |model| {
+            model.state() != ModelState::Failed && model.is_live()
+        }),
+        ("GET", "/readyz") => check(metrics, |model| model.state() == ModelState::Running),
+        ("GET", _) => Response::text("404 Not Found", "not found\n".to_string()),
+        _ => Response::text("405 Method Not Allowed", "method not allowed\n".to_string()),
+    };
+
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(response.body.as_bytes()).await?;
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+/// Reads the request line and headers, until the blank line
+async fn read_request(stream: &mut TcpStream) -> Result<String, anyhow::Error> {
+    let mut buffer = vec![0; HTTP_REQUEST_MAX_SIZE];
+    let mut read = 0;
+
+    while !buffer[..read]
+        .windows(4)
+        .any(|window| window == b"\r\n\r\n")
+    {
+        if read == buffer.len() {
+            return Err(anyhow::anyhow!("request too large"));
+        }
+
+        let count = stream.read(&mut buffer[read..]).await?;
+        if count == 0 {
+            return Err(anyhow::anyhow!("connection closed"));
+        }
+
+        read += count;
+    }
+
+    Ok(String::from_utf8_lossy(&buffer[..read]).into_owned())
+}
+
+/// 200 if every model passes the check, 503 with the models which don't otherwise
+fn check(metrics: &Metrics, model_check: fn(&ModelMetrics) -> bool) -> Response {
+    let body: String = metrics
+        .models()
+        .iter()
+        .filter(|(_, model)| !model_check(model))
+        .map(|(label, model)| format!("{} {:?}\n", label, model.state()))
+        .collect();
+
+    match body.is_empty() {
+        true => Response::text("200 OK", "ok\n".to_string()),
+        false => Response::text("503 Service Unavailable", body),
+    }
+}
+
+/// Runs the server until it fails, the engine keeps running without it
+pub(crate) async fn run(addr: SocketAddr, metrics: Arc<Metrics>) {
+    if let Err(e) = serve(addr, metrics).await {
+        error!("the HTTP server on {} stopped {:?}", addr, e);
+    }
+}
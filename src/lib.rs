@@ -1,16 +1,30 @@
-mod config;
 mod constants;
+mod http;
+mod retry;
 
+pub mod channel_pool;
+pub mod config;
 pub mod connector;
+pub mod engine;
+pub mod error;
+pub mod feed;
 pub mod messages;
+pub mod metrics;
 pub mod model;
 pub mod model_actor;
+pub mod model_definition;
+pub mod settings;
+pub mod share_policy;
+pub mod shutdown;
+pub mod trace;
 pub mod twin;
 pub mod twin_actor;
+pub mod twin_registry;
+pub mod validation;
 
 pub mod client {
     pub use iotics_grpc_client::properties;
-    pub use iotics_grpc_client::twin::UpsertFeedWithMeta;
+    pub use iotics_grpc_client::twin::{UpsertFeedWithMeta, UpsertInputWithMeta};
     pub use iotics_grpc_client::{
         FeedValue, GeoLocation, LangLiteral, Literal, Property, StringLiteral, Uri, Value,
     };
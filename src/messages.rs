@@ -1,15 +1,102 @@
-use actix::Message;
+use actix::dev::ToEnvelope;
+use actix::{Actor, Addr, Handler, MailboxError, Message};
 use std::time::{Duration, SystemTime};
 
 use iotics_grpc_client::Channel;
 
 use crate::connector::ConnectorData;
+use crate::engine::{EngineStatus, ModelStatus};
+use crate::retry::is_transport_error;
+use crate::trace::Span;
+
+/// Sends the message, waiting for room in the mailbox when it is full instead of dropping it
+pub(crate) async fn send_with_backpressure<A, M>(
+    addr: &Addr<A>,
+    message: M,
+) -> Result<(), MailboxError>
+where
+    A: Actor + Handler<M>,
+    A::Context: ToEnvelope<A, M>,
+    M: Message<Result = ()> + Send + 'static,
+{
+    match addr.try_send(message) {
+        Ok(()) => Ok(()),
+        Err(actix::prelude::SendError::Full(message)) => addr.send(message).await,
+        Err(actix::prelude::SendError::Closed(_)) => Err(MailboxError::Closed),
+    }
+}
 
 #[derive(Debug, Message)]
 #[rtype(result = "()")]
 pub struct ChannelsCreatedMessage {
     pub twin_channel: Channel,
     pub feed_channel: Channel,
+    /// Generation of the channels in the pool, to tell which ones a broken channel report is about
+    pub(crate) generation: u64,
+}
+
+impl ChannelsCreatedMessage {
+    pub fn new(twin_channel: Channel, feed_channel: Channel) -> Self {
+        Self {
+            twin_channel,
+            feed_channel,
+            generation: 0,
+        }
+    }
+}
+
+#[derive(Debug, Message, Clone)]
+#[rtype(result = "()")]
+pub struct ChannelsUpdated {
+    pub twin_channel: Channel,
+    pub feed_channel: Channel,
+}
+
+/// Outcome of the gRPC calls made over the channels, used to detect broken channels
+#[derive(Debug, Message, Default)]
+#[rtype(result = "()")]
+pub struct ChannelHealthReport {
+    pub successes: usize,
+    pub transport_errors: usize,
+}
+
+impl ChannelHealthReport {
+    pub fn record<T>(&mut self, result: &Result<T, anyhow::Error>) {
+        match result {
+            Ok(_) => self.successes += 1,
+            Err(e) if is_transport_error(e) => self.transport_errors += 1,
+            Err(_) => {}
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.successes == 0 && self.transport_errors == 0
+    }
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct ModelCreationFailure {
+    pub error: anyhow::Error,
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct ModelCreated {
+    pub model_did: String,
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct Reconcile {
+    /// `ConnectorData::id` of the twins in the source
+    pub twin_ids: Vec<String>,
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct DataStreamFailure {
+    pub error: anyhow::Error,
 }
 
 #[derive(Debug, Message)]
@@ -24,6 +111,20 @@ pub struct TwinData {
     pub model_did: String,
     pub data: ConnectorData,
     pub expire_time: SystemTime,
+    /// Span of the fetch the data comes from, then of its routing by the model actor
+    pub(crate) span: Span,
+}
+
+impl TwinData {
+    /// Twin data in the current span
+    pub fn new(model_did: String, data: ConnectorData, expire_time: SystemTime) -> Self {
+        Self {
+            model_did,
+            data,
+            expire_time,
+            span: Span::current(),
+        }
+    }
 }
 
 #[derive(Debug, Message, Clone)]
@@ -42,6 +143,22 @@ pub struct TwinCreationSuccess {
 #[derive(Debug, Message)]
 #[rtype(result = "()")]
 pub struct TwinCreationFailure {
+    pub twin_label: String,
+    /// The error would happen again on every attempt, e.g. invalid properties
+    pub permanent: bool,
+    pub error: anyhow::Error,
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct FeedShareFailed {
+    pub feed_id: String,
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct TwinQuarantined {
+    pub twin_seed: String,
     pub twin_label: String,
     pub error: anyhow::Error,
 }
@@ -65,6 +182,43 @@ pub struct ShareConcurrencyReduction {
     pub shares_count: usize,
 }
 
+/// Sent by a twin actor to itself once the properties of a `TwinData` are updated
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct PropertiesUpdated;
+
 #[derive(Debug, Message)]
 #[rtype(result = "()")]
 pub struct TwinDeleted;
+
+/// Sent by a twin actor to itself when the host doesn't know the twin it adopted from the registry
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct TwinNotFound;
+
+#[derive(Debug, Message, Clone)]
+#[rtype(result = "()")]
+pub struct Shutdown {
+    /// How long the twins are given to finish their in-flight shares
+    pub deadline: Duration,
+}
+
+#[derive(Debug, Message, Clone)]
+#[rtype(result = "()")]
+pub struct TwinShutdown {
+    pub delete_twins: bool,
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct ModelStopped {
+    pub model_seed: String,
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "ModelStatus")]
+pub struct GetStatus;
+
+#[derive(Debug, Message)]
+#[rtype(result = "EngineStatus")]
+pub struct GetEngineStatus;
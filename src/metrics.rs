@@ -0,0 +1,300 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::engine::ModelState;
+
+/// Counters and gauges of a model, updated by its model and twin actors.
+/// Served in the Prometheus text format by the engine, see `Engine::with_http_addr`.
+#[derive(Debug, Default)]
+pub struct ModelMetrics {
+    pub fetches: AtomicU64,
+    pub fetch_errors: AtomicU64,
+    /// Twins whose data couldn't be read by `get_data`
+    pub twin_data_errors: AtomicU64,
+    /// Total `get_data` duration, in microseconds
+    pub fetch_duration_us: AtomicU64,
+    pub twins_running: AtomicUsize,
+    pub twins_created: AtomicU64,
+    pub twins_failed: AtomicU64,
+    pub twins_deleted: AtomicU64,
+    pub shares: AtomicU64,
+    pub share_failures: AtomicU64,
+    pub reschedules: AtomicU64,
+    pub concurrent_new_twins: AtomicUsize,
+    pub concurrent_shares: AtomicUsize,
+    pub previously_unhandled_twins: AtomicUsize,
+    /// `TwinData` messages waiting to be handled by the model actor, the rescheduled ones included
+    pub queued_twin_data: AtomicUsize,
+    /// The health of the model is published here so the probes don't wait on the actor mailbox
+    state: AtomicU8,
+    /// Unix time in milliseconds, 0 if the model actor didn't start yet
+    started_at_ms: AtomicU64,
+    /// Unix time in milliseconds of the last fetch or share, 0 if there was none
+    last_activity_ms: AtomicU64,
+    liveness_window_ms: AtomicU64,
+}
+
+impl ModelMetrics {
+    pub fn record_fetch(&self, duration: Duration, success: bool) {
+        self.fetches.fetch_add(1, Ordering::Relaxed);
+        self.fetch_duration_us
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+
+        if !success {
+            self.fetch_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set(gauge: &AtomicUsize, value: usize) {
+        gauge.store(value, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add(gauge: &AtomicUsize, value: usize) {
+        gauge.fetch_add(value, Ordering::Relaxed);
+    }
+
+    /// Saturates at 0
+    pub(crate) fn sub(gauge: &AtomicUsize, value: usize) {
+        let _ = gauge.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+            Some(current.saturating_sub(value))
+        });
+    }
+
+    pub fn state(&self) -> ModelState {
+        match self.state.load(Ordering::Relaxed) {
+            1 => ModelState::Running,
+            2 => ModelState::RecreatingChannels,
+            3 => ModelState::ShuttingDown,
+            4 => ModelState::Failed,
+            _ => ModelState::Starting,
+        }
+    }
+
+    pub(crate) fn set_state(&self, state: ModelState) {
+        let state = match state {
+            ModelState::Starting => 0,
+            ModelState::Running => 1,
+            ModelState::RecreatingChannels => 2,
+            ModelState::ShuttingDown => 3,
+            ModelState::Failed => 4,
+        };
+
+        self.state.store(state, Ordering::Relaxed);
+    }
+
+    /// Called by the model actor when it (re)starts, the liveness window starts again
+    pub(crate) fn record_start(&self, liveness_window: Duration) {
+        self.set_state(ModelState::Starting);
+        self.started_at_ms.store(now_ms(), Ordering::Relaxed);
+        self.last_activity_ms.store(0, Ordering::Relaxed);
+        self.liveness_window_ms
+            .store(liveness_window.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// A fetch or a feed share succeeded
+    pub(crate) fn record_activity(&self) {
+        self.last_activity_ms.store(now_ms(), Ordering::Relaxed);
+    }
+
+    /// When data was last fetched or shared to a twin feed
+    pub fn last_activity(&self) -> Option<SystemTime> {
+        match self.last_activity_ms.load(Ordering::Relaxed) {
+            0 => None,
+            last_activity => Some(UNIX_EPOCH + Duration::from_millis(last_activity)),
+        }
+    }
+
+    /// Whether data was fetched or shared within the liveness window, or since the model actor
+    /// started if there was none yet
+    pub fn is_live(&self) -> bool {
+        let since = self
+            .last_activity_ms
+            .load(Ordering::Relaxed)
+            .max(self.started_at_ms.load(Ordering::Relaxed));
+
+        since == 0
+            || now_ms().saturating_sub(since) <= self.liveness_window_ms.load(Ordering::Relaxed)
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// The metrics of the models of an engine
+#[derive(Debug, Default)]
+pub struct Metrics {
+    models: Mutex<Vec<MetricsEntry>>,
+}
+
+#[derive(Debug)]
+struct MetricsEntry {
+    seed: String,
+    label: String,
+    metrics: Arc<ModelMetrics>,
+}
+
+impl Metrics {
+    /// The metrics of the model with the given seed, created on first use.
+    /// The models are unique by seed, two of them can have the same label.
+    pub fn model(&self, seed: &str, label: &str) -> Arc<ModelMetrics> {
+        let mut models = self.models.lock().expect("metrics lock poisoned");
+
+        if let Some(entry) = models.iter().find(|entry| entry.seed == seed) {
+            return entry.metrics.clone();
+        }
+
+        let metrics = Arc::new(ModelMetrics::default());
+        models.push(MetricsEntry {
+            seed: seed.to_string(),
+            label: label.to_string(),
+            metrics: metrics.clone(),
+        });
+
+        metrics
+    }
+
+    /// The labels and metrics of the models
+    pub(crate) fn models(&self) -> Vec<(String, Arc<ModelMetrics>)> {
+        self.models
+            .lock()
+            .expect("metrics lock poisoned")
+            .iter()
+            .map(|entry| (entry.label.clone(), entry.metrics.clone()))
+            .collect()
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let models = self.models.lock().expect("metrics lock poisoned");
+        let mut output = String::new();
+
+        let mut family =
+            |name: &str, kind: &str, help: &str, value: &dyn Fn(&ModelMetrics) -> f64| {
+                let _ = writeln!(output, "# HELP {name} {help}");
+                let _ = writeln!(output, "# TYPE {name} {kind}");
+
+                for entry in models.iter() {
+                    let _ = writeln!(
+                        output,
+                        "{name}{{model=\"{}\",seed=\"{}\"}} {}",
+                        escape_label(&entry.label),
+                        escape_label(&entry.seed),
+                        value(&entry.metrics)
+                    );
+                }
+            };
+
+        let load = |value: &AtomicU64| value.load(Ordering::Relaxed) as f64;
+        let load_gauge = |value: &AtomicUsize| value.load(Ordering::Relaxed) as f64;
+
+        family(
+            "iotics_connector_fetches_total",
+            "counter",
+            "Calls to get_data",
+            &|m| load(&m.fetches),
+        );
+        family(
+            "iotics_connector_fetch_errors_total",
+            "counter",
+            "Calls to get_data which returned an error",
+            &|m| load(&m.fetch_errors),
+        );
+        family(
+            "iotics_connector_twin_data_errors_total",
+            "counter",
+            "Twins whose data get_data couldn't read",
+            &|m| load(&m.twin_data_errors),
+        );
+        family(
+            "iotics_connector_fetch_duration_seconds_total",
+            "counter",
+            "Time spent in get_data",
+            &|m| load(&m.fetch_duration_us) / 1_000_000.0,
+        );
+        family(
+            "iotics_connector_twins_running",
+            "gauge",
+            "Running twin actors",
+            &|m| load_gauge(&m.twins_running),
+        );
+        family(
+            "iotics_connector_twins_created_total",
+            "counter",
+            "Twins upserted on the host",
+            &|m| load(&m.twins_created),
+        );
+        family(
+            "iotics_connector_twins_failed_total",
+            "counter",
+            "Twins which couldn't be created",
+            &|m| load(&m.twins_failed),
+        );
+        family(
+            "iotics_connector_twins_deleted_total",
+            "counter",
+            "Twins deleted from the host",
+            &|m| load(&m.twins_deleted),
+        );
+        family(
+            "iotics_connector_shares_total",
+            "counter",
+            "Feed values shared",
+            &|m| load(&m.shares),
+        );
+        family(
+            "iotics_connector_share_failures_total",
+            "counter",
+            "Feed values which couldn't be shared",
+            &|m| load(&m.share_failures),
+        );
+        family(
+            "iotics_connector_reschedules_total",
+            "counter",
+            "Twin data rescheduled by the throttling",
+            &|m| load(&m.reschedules),
+        );
+        family(
+            "iotics_connector_concurrent_new_twins",
+            "gauge",
+            "Twins being created",
+            &|m| load_gauge(&m.concurrent_new_twins),
+        );
+        family(
+            "iotics_connector_concurrent_shares",
+            "gauge",
+            "Feed shares in flight",
+            &|m| load_gauge(&m.concurrent_shares),
+        );
+        family(
+            "iotics_connector_previously_unhandled_twins",
+            "gauge",
+            "Twins whose data expired before being handled in the last fetch",
+            &|m| load_gauge(&m.previously_unhandled_twins),
+        );
+        family(
+            "iotics_connector_queued_twin_data",
+            "gauge",
+            "Twin data waiting in the model actor mailbox",
+            &|m| load_gauge(&m.queued_twin_data),
+        );
+
+        output
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -1,10 +1,13 @@
+use std::collections::HashMap;
+
 use iotics_grpc_client::properties::PropertyBuilder;
 
 use iotics_grpc_client::properties::common_keys;
-use iotics_grpc_client::twin::UpsertFeedWithMeta;
+use iotics_grpc_client::twin::{UpsertFeedWithMeta, UpsertInputWithMeta};
 use iotics_grpc_client::{FeedValue, Property};
 
 use crate::constants::{LANGUAGE, MAX_LABEL_LENGTH};
+use crate::share_policy::SharePolicy;
 
 #[derive(Debug, Clone)]
 pub struct Model {
@@ -12,7 +15,10 @@ pub struct Model {
     label_prefix: String,
     model_properties: Vec<Property>,
     feeds: Vec<UpsertFeedWithMeta>,
+    inputs: Vec<UpsertInputWithMeta>,
     twin_properties: Vec<Property>,
+    share_policy: SharePolicy,
+    feed_share_policies: HashMap<String, SharePolicy>,
 }
 
 impl Model {
@@ -28,10 +34,37 @@ impl Model {
             label_prefix,
             model_properties,
             feeds,
+            inputs: Vec::new(),
             twin_properties,
+            share_policy: SharePolicy::default(),
+            feed_share_policies: HashMap::new(),
         }
     }
 
+    /// Declares inputs on the twins, the messages they receive are passed to `Connector::on_input`
+    pub fn with_inputs(mut self, inputs: Vec<UpsertInputWithMeta>) -> Self {
+        self.inputs = inputs;
+        self
+    }
+
+    /// Sets the share policy of the feeds which don't have their own
+    pub fn with_share_policy(mut self, share_policy: SharePolicy) -> Self {
+        self.share_policy = share_policy;
+        self
+    }
+
+    pub fn with_feed_share_policy(mut self, feed_id: &str, share_policy: SharePolicy) -> Self {
+        self.feed_share_policies
+            .insert(feed_id.to_string(), share_policy);
+        self
+    }
+
+    pub fn get_share_policy(&self, feed_id: &str) -> &SharePolicy {
+        self.feed_share_policies
+            .get(feed_id)
+            .unwrap_or(&self.share_policy)
+    }
+
     pub fn get_seed(&self) -> String {
         format!("{} Model", self.seed_prefix)
     }
@@ -89,6 +122,14 @@ impl Model {
         }
     }
 
+    pub fn get_feed(&self, feed_id: &str) -> Option<&UpsertFeedWithMeta> {
+        self.feeds.iter().find(|feed| feed.id == feed_id)
+    }
+
+    pub fn get_inputs(&self) -> Vec<UpsertInputWithMeta> {
+        self.inputs.clone()
+    }
+
     pub fn get_model_properties(&self) -> &Vec<Property> {
         &self.model_properties
     }
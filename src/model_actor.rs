@@ -1,10 +1,18 @@
-use std::cmp::{self, max};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
-use actix::clock::{interval, sleep};
-use actix::{Actor, Addr, AsyncContext, Context, Handler, System, WrapFuture};
+use actix::clock::{interval, sleep, timeout};
+use actix::prelude::SendError;
+use actix::{
+    Actor, ActorContext, ActorFutureExt, Addr, AsyncContext, Context, Handler, Recipient,
+    ResponseActFuture, SpawnHandle, System, WrapFuture,
+};
+use futures::StreamExt;
+use iotics_grpc_client::interest::follow_with_channel;
+use iotics_grpc_client::properties::{common_keys, PropertyBuilder};
+use iotics_grpc_client::search::{search_with_channel, Scope, SearchFilter};
+use iotics_grpc_client::twin::crud::delete_twin_with_channel;
 use iotics_grpc_client::twin::share::share_data_with_channel;
 use iotics_grpc_client::twin::upsert::upsert_twin_with_channel;
 use iotics_identity::create_twin_did_with_control_delegation;
@@ -12,21 +20,30 @@ use log::{debug, error, info, warn};
 use serde_json::json;
 use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 
-use iotics_grpc_client::{create_channel, Channel};
+use iotics_grpc_client::Channel;
 
+use crate::channel_pool::ChannelPool;
 use crate::config::AuthBuilder;
-use crate::connector::Connector;
-use crate::constants::{
-    AGENT_TWIN_NAME, CLEANUP_INTERVAL_MULTIPLIER, CONCURRENT_NEW_TWINS_LIMIT,
-    CONCURRENT_SHARES_LIMIT, NEW_TWINS_SHARE_TICK_CAP, RESCHEDULE_DELAY,
+use crate::connector::{
+    parse_message_data, Connector, FetchResult, FollowedData, StreamingConnector,
 };
+use crate::constants::{AGENT_TWIN_NAME, SEARCH_TIMEOUT, SHUTDOWN_POLL_INTERVAL};
+use crate::engine::{ModelState, ModelStatus};
+use crate::error::{EngineError, ErrorHandler};
 use crate::messages::{
-    ChannelsCreatedMessage, Cleanup, GetData, HeartbeatData, ShareConcurrencyReduction,
-    TwinConcurrencyReduction, TwinData,
+    send_with_backpressure, ChannelHealthReport, ChannelsCreatedMessage, ChannelsUpdated, Cleanup,
+    DataStreamFailure, GetData, GetStatus, HeartbeatData, ModelCreated, ModelCreationFailure,
+    ModelStopped, Reconcile, ShareConcurrencyReduction, Shutdown, TwinConcurrencyReduction,
+    TwinData, TwinQuarantined, TwinShutdown,
 };
+use crate::metrics::ModelMetrics;
 use crate::model::Model;
+use crate::retry::{resubscribe_with_backoff, retry_with_backoff};
+use crate::settings::ModelSettings;
+use crate::trace::{span, Instrument, Span};
 use crate::twin::Twin;
-use crate::twin_actor::TwinActor;
+use crate::twin_actor::{TwinActor, TwinContext};
+use crate::twin_registry::TwinRegistry;
 
 #[derive(Debug, Clone)]
 pub struct TwinActorInfo {
@@ -39,88 +56,336 @@ pub struct ModelActor {
     auth_builder: Arc<AuthBuilder>,
     model: Model,
     data_getter: Arc<dyn Connector>,
-    fetch_every_secs: u64,
-    delete_twins: bool,
+    streamer: Option<Arc<dyn StreamingConnector>>,
+    settings: ModelSettings,
     twins: HashMap<String, TwinActorInfo>,
     twin_channel: Option<Channel>,
     feed_channel: Option<Channel>,
     concurrent_new_twins: usize,
     concurrent_shares: usize,
     previously_unhandled_twins: usize,
+    recreating_channels: bool,
+    consecutive_transport_errors: usize,
+    quarantined_twins: HashMap<String, SystemTime>,
+    model_did: Option<String>,
+    last_heartbeat_shares: u64,
+    shutting_down: bool,
+    channel_pool: Arc<ChannelPool>,
+    channels_generation: u64,
+    supervisor: Option<Recipient<ModelStopped>>,
+    follow_subscriptions: Vec<SpawnHandle>,
+    reconciled: bool,
+    registry: Option<Arc<TwinRegistry>>,
+    metrics: Arc<ModelMetrics>,
+    error_handler: ErrorHandler,
 }
 
 impl ModelActor {
     pub fn new(
+        auth_builder: Arc<AuthBuilder>,
         model: Model,
-        fetch_every_secs: u64,
         data_getter: Arc<dyn Connector>,
-        delete_twins: bool,
+        settings: ModelSettings,
     ) -> Self {
-        let auth_builder = AuthBuilder::new();
+        let channel_pool = ChannelPool::new(auth_builder.clone());
+        let registry = settings
+            .registry_path
+            .as_ref()
+            .map(|path| Arc::new(TwinRegistry::open(path)));
 
         Self {
             auth_builder,
             model,
-            fetch_every_secs,
             data_getter,
-            delete_twins,
+            streamer: None,
+            settings,
             twins: HashMap::new(),
             twin_channel: None,
             feed_channel: None,
             concurrent_new_twins: 0,
             concurrent_shares: 0,
             previously_unhandled_twins: 0,
+            recreating_channels: false,
+            consecutive_transport_errors: 0,
+            quarantined_twins: HashMap::new(),
+            model_did: None,
+            last_heartbeat_shares: 0,
+            shutting_down: false,
+            channel_pool,
+            channels_generation: 0,
+            supervisor: None,
+            follow_subscriptions: Vec::new(),
+            reconciled: false,
+            registry,
+            metrics: Arc::new(ModelMetrics::default()),
+            error_handler: ErrorHandler::default(),
         }
     }
-}
 
-impl Actor for ModelActor {
-    type Context = Context<Self>;
+    /// Consumes the stream of the connector instead of polling `get_data` every `fetch_every_secs`
+    pub fn new_streaming<C: StreamingConnector + 'static>(
+        auth_builder: Arc<AuthBuilder>,
+        model: Model,
+        connector: Arc<C>,
+        settings: ModelSettings,
+    ) -> Self {
+        Self::new(auth_builder, model, connector.clone(), settings).with_streamer(connector)
+    }
 
-    fn started(&mut self, ctx: &mut Self::Context) {
-        ctx.set_mailbox_capacity(32768);
-        let model_label = self.model.get_label();
-        info!("[{}] Model actor started", &model_label);
+    pub(crate) fn with_streamer(mut self, streamer: Arc<dyn StreamingConnector>) -> Self {
+        self.streamer = Some(streamer);
+        self
+    }
 
-        let auth_builder = self.auth_builder.clone();
+    /// Shares the channels of the given pool instead of creating dedicated ones
+    pub fn with_channel_pool(mut self, channel_pool: Arc<ChannelPool>) -> Self {
+        self.channel_pool = channel_pool;
+        self
+    }
+
+    /// Updates the given metrics instead of unregistered ones
+    pub fn with_metrics(mut self, metrics: Arc<ModelMetrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Reports the errors to the given handler instead of only logging them
+    pub fn with_error_handler(mut self, error_handler: ErrorHandler) -> Self {
+        self.error_handler = error_handler;
+        self
+    }
+
+    /// Reports the actor stopping to the supervisor instead of stopping the `System`
+    pub(crate) fn with_supervisor(mut self, supervisor: Recipient<ModelStopped>) -> Self {
+        self.supervisor = Some(supervisor);
+        self
+    }
+
+    fn state(&self) -> ModelState {
+        if self.shutting_down {
+            ModelState::ShuttingDown
+        } else if self.recreating_channels {
+            ModelState::RecreatingChannels
+        } else if self.model_did.is_some() {
+            ModelState::Running
+        } else {
+            ModelState::Starting
+        }
+    }
+
+    /// Makes the state readable from the metrics, without messaging the actor
+    fn publish_state(&self) {
+        self.metrics.set_state(self.state());
+    }
+
+    /// The model isn't live when nothing was fetched or shared for this long
+    fn liveness_window(&self) -> Duration {
+        Duration::from_secs(self.settings.fetch_every_secs)
+            .mul_f64(self.settings.liveness_multiplier)
+    }
+
+    fn twin_context(&self, twin_channel: Channel, feed_channel: Channel) -> TwinContext {
+        TwinContext {
+            auth_builder: self.auth_builder.clone(),
+            model: self.model.clone(),
+            connector: self.data_getter.clone(),
+            settings: self.settings.clone(),
+            twin_channel,
+            feed_channel,
+            registry: self.registry.clone(),
+            metrics: self.metrics.clone(),
+        }
+    }
+
+    /// Gets the twin and feed channels from the pool, retrying with backoff,
+    /// and sends them back to the actor in a `ChannelsCreatedMessage`.
+    /// The channels of `broken_generation` are recreated if set.
+    fn create_channels(&mut self, ctx: &mut Context<Self>, broken_generation: Option<u64>) {
+        let model_label = self.model.get_label();
+        let channel_pool = self.channel_pool.clone();
+        let channel_backoff = self.settings.channel_backoff.clone();
         let addr = ctx.address();
 
-        // create the channels
         let fut = async move {
-            let twin_channel = create_channel(auth_builder.clone(), None, None, None)
-                .await
-                .unwrap_or_else(|e| {
-                    panic!(
-                        "[{}] failed to create the twin channel: {:?}",
-                        &model_label, e
-                    )
-                });
-            let feed_channel = create_channel(auth_builder.clone(), None, None, None)
-                .await
-                .unwrap_or_else(|e| {
-                    panic!(
-                        "[{}] failed to create the feed channel: {:?}",
-                        &model_label, e
-                    )
-                });
+            let description = format!("[{model_label}] channels creation");
 
-            addr.send(ChannelsCreatedMessage {
-                twin_channel,
-                feed_channel,
-            })
-            .await
-            .expect("failed to send message");
+            let result = match broken_generation {
+                Some(generation) => {
+                    channel_pool
+                        .recreate(generation, &channel_backoff, &description)
+                        .await
+                }
+                None => channel_pool.get(&channel_backoff, &description).await,
+            };
+
+            match result {
+                Ok(channels) => {
+                    addr.do_send(ChannelsCreatedMessage {
+                        twin_channel: channels.twin_channel,
+                        feed_channel: channels.feed_channel,
+                        generation: channels.generation,
+                    });
+                }
+                Err(error) => {
+                    addr.do_send(ModelCreationFailure { error });
+                }
+            }
         }
         .into_actor(self);
 
         ctx.spawn(fut);
     }
 
+    /// Handles the data again after the throttling delay
+    fn reschedule(&self, ctx: &mut Context<Self>, message: TwinData) {
+        Span::current().record("decision", "rescheduled");
+        ModelMetrics::increment(&self.metrics.reschedules);
+        ModelMetrics::add(&self.metrics.queued_twin_data, 1);
+
+        ctx.notify_later(message, self.settings.throttling.reschedule_delay());
+    }
+
+    fn update_concurrency_metrics(&self) {
+        ModelMetrics::set(
+            &self.metrics.concurrent_new_twins,
+            self.concurrent_new_twins,
+        );
+        ModelMetrics::set(&self.metrics.concurrent_shares, self.concurrent_shares);
+    }
+
+    fn save_registry(&self) {
+        if let Some(registry) = self.registry.as_ref() {
+            if let Err(e) = registry.save() {
+                error!(
+                    "[{}] failed to save the twin registry {:?}",
+                    &self.model.get_label(),
+                    e
+                );
+            }
+        }
+    }
+
+    /// Follows the remote feeds of the settings with the model twin and passes the shared values
+    /// to the connector. The subscriptions are re-opened with the channel backoff when they fail or end.
+    fn follow_feeds(&mut self, ctx: &mut Context<Self>) {
+        for handle in self.follow_subscriptions.drain(..) {
+            ctx.cancel_future(handle);
+        }
+
+        let (model_did, feed_channel) = match (&self.model_did, &self.feed_channel) {
+            (Some(model_did), Some(feed_channel)) => (model_did.clone(), feed_channel.clone()),
+            _ => return,
+        };
+
+        for followed in self.settings.follow.clone() {
+            let auth_builder = self.auth_builder.clone();
+            let feed_channel = feed_channel.clone();
+            let model_did = model_did.clone();
+            let connector = self.data_getter.clone();
+            let model_label = self.model.get_label();
+            let backoff = self.settings.channel_backoff.clone();
+            let addr = ctx.address();
+
+            let fut = async move {
+                let description = format!(
+                    "[{}] follow of {} feed of {}",
+                    &model_label, &followed.feed_id, &followed.twin_did
+                );
+                let connector = &connector;
+                let followed = &followed;
+
+                resubscribe_with_backoff(
+                    &backoff,
+                    &description,
+                    || {
+                        auth_builder.retry_on_auth_error(|| {
+                            follow_with_channel(
+                                auth_builder.clone(),
+                                feed_channel.clone(),
+                                &model_did,
+                                &followed.twin_did,
+                                &followed.feed_id,
+                                true,
+                            )
+                        })
+                    },
+                    |result| {
+                        let mut health_report = ChannelHealthReport::default();
+                        health_report.record(result);
+                        addr.do_send(health_report);
+                    },
+                    move |response| {
+                        let data = response
+                            .payload
+                            .and_then(|payload| payload.feed_data)
+                            .map(|feed_data| feed_data.data);
+
+                        async move {
+                            if let Some(data) = data {
+                                connector
+                                    .on_followed_data(FollowedData {
+                                        twin_did: followed.twin_did.clone(),
+                                        feed_id: followed.feed_id.clone(),
+                                        value: parse_message_data(&data),
+                                    })
+                                    .await;
+                            }
+                        }
+                    },
+                )
+                .await;
+            }
+            .into_actor(self);
+
+            self.follow_subscriptions.push(ctx.spawn(fut));
+        }
+    }
+}
+
+impl Actor for ModelActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.set_mailbox_capacity(32768);
+        info!("[{}] Model actor started", &self.model.get_label());
+
+        if let Some(registry) = self.registry.as_ref() {
+            info!(
+                "[{}] {} twins in the registry",
+                &self.model.get_label(),
+                registry.len()
+            );
+        }
+
+        self.metrics.record_start(self.liveness_window());
+        self.create_channels(ctx, None);
+    }
+
     fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.save_registry();
+
+        if self.shutting_down {
+            info!("[{}] Model actor stopped", &self.model.get_label());
+            return;
+        }
+
         error!("[{}] Model actor stopped", &self.model.get_label());
 
-        // currently stopping the system if the upsert of the model fails
-        // TODO: add some retry mechanism
+        if let Some(supervisor) = self.supervisor.as_ref() {
+            // the restarted model actor starts its own twin actors
+            for twin_actor in self.twins.values() {
+                twin_actor.addr.do_send(TwinShutdown {
+                    delete_twins: false,
+                });
+            }
+
+            supervisor.do_send(ModelStopped {
+                model_seed: self.model.get_seed(),
+            });
+            return;
+        }
+
+        // the model actor only stops if the model or the channels couldn't be created, even after retrying
         System::current().stop_with_code(1);
     }
 }
@@ -133,64 +398,141 @@ impl Handler<ChannelsCreatedMessage> for ModelActor {
         let feed_channel = message.feed_channel;
 
         self.twin_channel.replace(twin_channel.clone());
-        self.feed_channel.replace(feed_channel);
+        self.feed_channel.replace(feed_channel.clone());
+        self.channels_generation = message.generation;
+
+        if self.recreating_channels {
+            info!("[{}] Channels recreated", &self.model.get_label());
+            self.recreating_channels = false;
+            self.publish_state();
+
+            // the running twins keep using the broken channels otherwise
+            for twin_actor in self.twins.values() {
+                if twin_actor.addr.connected() {
+                    twin_actor.addr.do_send(ChannelsUpdated {
+                        twin_channel: twin_channel.clone(),
+                        feed_channel: feed_channel.clone(),
+                    });
+                }
+            }
+
+            // the follow subscriptions are on the broken channels too
+            self.follow_feeds(ctx);
+            return;
+        }
 
         let addr = ctx.address();
 
         let auth_builder = self.auth_builder.clone();
-        let identity_config = auth_builder
-            .get_identity_config()
-            .expect("failed to get identity config");
+        let identity_config = match auth_builder.get_identity_config() {
+            Ok(identity_config) => identity_config,
+            Err(error) => {
+                self.error_handler.report(EngineError::Identity {
+                    model: self.model.get_label(),
+                    error,
+                });
+                ctx.stop();
+                return;
+            }
+        };
         let model = self.model.clone();
-        let fetch_every_secs = self.fetch_every_secs;
+        let streamer = self.streamer.clone();
+        let settings = self.settings.clone();
+        let metrics = self.metrics.clone();
+        let fetch_every_secs = self.settings.fetch_every_secs;
+        let startup_backoff = self.settings.startup_backoff.clone();
 
         // upsert the model and start the update interval
         let fut = async move {
-            let result = async {
+            let model_label = model.get_label();
+
+            let description = format!("[{model_label}] model creation");
+
+            let result = retry_with_backoff(&startup_backoff, &description, || async {
                 let model_did = create_twin_did_with_control_delegation(
                     &identity_config,
                     &model.get_seed(),
                     AGENT_TWIN_NAME,
                 )?;
 
-                upsert_twin_with_channel(
-                    auth_builder,
-                    twin_channel,
-                    &model_did,
-                    model.get_model_properties().clone(),
-                    model.get_feeds(true),
-                    Vec::new(),
-                    None,
-                )
-                .await?;
+                auth_builder
+                    .retry_on_auth_error(|| {
+                        upsert_twin_with_channel(
+                            auth_builder.clone(),
+                            twin_channel.clone(),
+                            &model_did,
+                            model.get_model_properties().clone(),
+                            model.get_feeds(true),
+                            Vec::new(),
+                            None,
+                        )
+                    })
+                    .await?;
 
                 Ok::<String, anyhow::Error>(model_did)
-            };
-
-            let model_label = model.get_label();
+            });
 
             match result.await {
                 Ok(model_did) => {
                     debug!("[{}] model did {}", &model_label, &model_did);
 
+                    addr.do_send(ModelCreated {
+                        model_did: model_did.clone(),
+                    });
+
+                    if let Some(streamer) = streamer {
+                        // share the current state first, then whatever the source pushes
+                        if addr
+                            .send(GetData {
+                                model_did: model_did.clone(),
+                            })
+                            .await
+                            .is_err()
+                        {
+                            // the actor stopped
+                            return;
+                        }
+
+                        let result = consume_stream(
+                            &addr,
+                            streamer,
+                            &model_did,
+                            &model_label,
+                            &settings,
+                            &metrics,
+                        )
+                        .await;
+
+                        if let Err(error) = result {
+                            addr.do_send(DataStreamFailure { error });
+                        }
+
+                        return;
+                    }
+
                     loop {
                         // start the fetch data timer
                         let now = SystemTime::now();
 
-                        addr.send(GetData {
-                            model_did: model_did.clone(),
-                        })
-                        .await
-                        .unwrap_or_else(|_| panic!("[{}] failed to send message", &model_label));
+                        if addr
+                            .send(GetData {
+                                model_did: model_did.clone(),
+                            })
+                            .await
+                            .is_err()
+                        {
+                            // the actor stopped
+                            return;
+                        }
 
-                        let elapsed = now.elapsed().expect("this should not happen").as_secs();
-                        let sleep_amount = max(0, fetch_every_secs - elapsed);
+                        let elapsed = now.elapsed().unwrap_or_default().as_secs();
+                        let sleep_amount = fetch_every_secs.saturating_sub(elapsed);
 
                         sleep(Duration::from_secs(sleep_amount)).await;
                     }
                 }
-                Err(e) => {
-                    panic!("[{}] failed to create model: {}", &model_label, e);
+                Err(error) => {
+                    addr.do_send(ModelCreationFailure { error });
                 }
             }
         }
@@ -201,20 +543,30 @@ impl Handler<ChannelsCreatedMessage> for ModelActor {
         // start the cleanup timer
         let addr = ctx.address();
         let model_label = self.model.get_label();
-        let delete_twins = self.delete_twins;
+        let delete_twins = self.settings.delete_twins;
+        let cleanup_interval_multiplier = self.settings.cleanup_interval_multiplier;
+        let error_handler = self.error_handler.clone();
 
         let fut = async move {
             let cleanup_every_secs =
-                Duration::from_secs((fetch_every_secs as f64 * CLEANUP_INTERVAL_MULTIPLIER) as u64);
+                Duration::from_secs((fetch_every_secs as f64 * cleanup_interval_multiplier) as u64);
             let mut interval = interval(cleanup_every_secs);
 
             loop {
                 interval.tick().await;
-                addr.try_send(Cleanup {
+
+                // skip a cleanup rather than piling them up behind the twin data
+                match addr.try_send(Cleanup {
                     delete_twins,
                     cleanup_every_secs,
-                })
-                .unwrap_or_else(|_| panic!("[{}] failed to send twins cleanup", &model_label));
+                }) {
+                    Ok(()) => {}
+                    Err(SendError::Full(_)) => error_handler.report(EngineError::MailboxFull {
+                        model: model_label.clone(),
+                        message: "Cleanup",
+                    }),
+                    Err(SendError::Closed(_)) => return,
+                }
             }
         }
         .into_actor(self);
@@ -223,40 +575,102 @@ impl Handler<ChannelsCreatedMessage> for ModelActor {
     }
 }
 
+impl Handler<ModelCreationFailure> for ModelActor {
+    type Result = ();
+
+    fn handle(&mut self, message: ModelCreationFailure, ctx: &mut Context<Self>) -> Self::Result {
+        self.error_handler.report(EngineError::ModelCreation {
+            model: self.model.get_label(),
+            error: message.error,
+        });
+
+        ctx.stop();
+    }
+}
+
+impl Handler<ModelCreated> for ModelActor {
+    type Result = ();
+
+    fn handle(&mut self, message: ModelCreated, ctx: &mut Context<Self>) -> Self::Result {
+        self.model_did.replace(message.model_did);
+        self.publish_state();
+        self.follow_feeds(ctx);
+    }
+}
+
+impl Handler<DataStreamFailure> for ModelActor {
+    type Result = ();
+
+    fn handle(&mut self, message: DataStreamFailure, ctx: &mut Context<Self>) -> Self::Result {
+        self.error_handler.report(EngineError::DataStream {
+            model: self.model.get_label(),
+            error: message.error,
+        });
+
+        ctx.stop();
+    }
+}
+
 impl Handler<GetData> for ModelActor {
     type Result = ();
 
     fn handle(&mut self, message: GetData, ctx: &mut Context<Self>) -> Self::Result {
+        if self.shutting_down {
+            return;
+        }
+
         let model_label = self.model.get_label();
 
         let addr = ctx.address();
         let model_did = message.model_did;
+        self.model_did.replace(model_did.clone());
+        self.publish_state();
         let twins = self.twins.clone();
         let data_getter = self.data_getter.clone();
-        let fetch_every_secs = self.fetch_every_secs;
+        let fetch_every_secs = self.settings.fetch_every_secs;
+        let new_twins_share_tick_cap = self.settings.throttling.new_twins_share_tick_cap;
         let concurrent_new_twins = self.concurrent_new_twins;
         let concurrent_shares = self.concurrent_shares;
         let previously_unhandled_twins = self.previously_unhandled_twins;
+        let reconcile = self.settings.reconcile_twins && !self.reconciled;
+        let metrics = self.metrics.clone();
+        let error_handler = self.error_handler.clone();
+
+        ModelMetrics::set(
+            &metrics.previously_unhandled_twins,
+            previously_unhandled_twins,
+        );
 
         // reset previously_unhandled_twins
         self.previously_unhandled_twins = 0;
 
+        let fetch_span = span!("fetch", model = %model_label, twins = tracing::field::Empty);
+        let get_data_span = span!(parent: &fetch_span, "get_data", model = %model_label);
+
         let fut = async move {
             info!("[{}] Requesting data", &model_label);
-            let results = data_getter.get_data().await;
+            let started_at = Instant::now();
+            let results = data_getter.get_data().instrument(get_data_span).await;
+            metrics.record_fetch(started_at.elapsed(), results.is_ok());
 
             // Allow creating new Twin Actors and sharing data to existing Twin Actors
             // only for a specific time period for better host performance
             let expire_after_secs =
-                (fetch_every_secs as f64 * NEW_TWINS_SHARE_TICK_CAP) as u64;
+                (fetch_every_secs as f64 * new_twins_share_tick_cap) as u64;
             let expire_time = SystemTime::now()
-                .checked_add(Duration::from_secs(expire_after_secs)).unwrap_or_else(|| {
-                    panic!("[{}] this should not happen", &model_label)
-                });
+                .checked_add(Duration::from_secs(expire_after_secs))
+                .unwrap_or_else(SystemTime::now);
 
             match results {
-                Ok(results) => {
-                    info!("[{}] Got data for {} twins", &model_label, results.len());
+                Ok(FetchResult { mut data, errors }) => {
+                    metrics.record_activity();
+                    Span::current().record("twins", data.len() as u64);
+                    info!(
+                        "[{}] Got data for {} twins, {} twins failed",
+                        &model_label,
+                        data.len(),
+                        errors.len()
+                    );
                     info!(
                         "[{}] There are {} twins currently running, {} unhandled twins in the last run", &model_label,
                         twins.len(),
@@ -271,31 +685,225 @@ impl Handler<GetData> for ModelActor {
                         );
                     }
 
+                    // the twins which failed are still in the source
+                    let twin_ids: Vec<String> = data
+                        .iter()
+                        .map(|data| data.id.clone())
+                        .chain(errors.iter().map(|error| error.twin_id.clone()))
+                        .collect();
+
+                    for error in errors {
+                        ModelMetrics::increment(&metrics.twin_data_errors);
+
+                        // shared like the data of the other twins, to the twin in error
+                        if let Some(mut status) = error.status {
+                            status.id = error.twin_id.clone();
+                            data.push(status);
+                        }
+
+                        error_handler.report(EngineError::TwinData {
+                            model: model_label.clone(),
+                            twin_id: error.twin_id,
+                            error: error.error,
+                        });
+                    }
+
+                    // an empty result is more likely a source issue than every twin being gone
+                    if reconcile && !twin_ids.is_empty() {
+                        addr.do_send(Reconcile { twin_ids });
+                    }
+
                     let mut shares = 0;
 
-                    for data in results {
-                        addr.try_send(TwinData {
-                            model_did: model_did.clone(),
-                            data,
-                            expire_time,
-                        }).unwrap_or_else(|_| {
-                            panic!("[{}] failed to send received data", &model_label)
-                        });
+                    // waits for room in the mailbox when it is full instead of dropping the data
+                    for data in data {
+                        ModelMetrics::add(&metrics.queued_twin_data, 1);
+                        let result = send_with_backpressure(
+                            &addr,
+                            TwinData::new(model_did.clone(), data, expire_time),
+                        )
+                        .await;
+
+                        if result.is_err() {
+                            error_handler.report(EngineError::MailboxClosed {
+                                model: model_label,
+                                message: "TwinData",
+                            });
+                            return;
+                        }
 
                         shares += 1;
                     }
 
-                    addr.try_send(HeartbeatData {
+                    let result = send_with_backpressure(&addr, HeartbeatData {
                         model_did: model_did.clone(),
                         shares,
-                    }).unwrap_or_else(|_| {
-                        panic!("[{}] failed to send received data", &model_label)
+                    }).await;
+
+                    if result.is_err() {
+                        error_handler.report(EngineError::MailboxClosed {
+                            model: model_label,
+                            message: "HeartbeatData",
+                        });
+                    }
+                }
+                Err(error) => {
+                    error_handler.report(EngineError::Fetch {
+                        model: model_label,
+                        error,
                     });
                 }
+            };
+        }
+        .instrument(fetch_span)
+        .into_actor(self);
+
+        ctx.spawn(fut);
+    }
+}
+
+impl Handler<Reconcile> for ModelActor {
+    type Result = ();
+
+    fn handle(&mut self, message: Reconcile, ctx: &mut Context<Self>) -> Self::Result {
+        if self.reconciled {
+            return;
+        }
+
+        let (model_did, twin_channel) = match (&self.model_did, &self.twin_channel) {
+            (Some(model_did), Some(twin_channel)) => (model_did.clone(), twin_channel.clone()),
+            _ => return,
+        };
+
+        self.reconciled = true;
+
+        let model_label = self.model.get_label();
+        let auth_builder = self.auth_builder.clone();
+        let connector = self.data_getter.clone();
+        let startup_backoff = self.settings.startup_backoff.clone();
+        let delete_twins = self.settings.delete_twins;
+        let registry = self.registry.clone();
+        let metrics = self.metrics.clone();
+        let twin_seeds: Vec<String> = message
+            .twin_ids
+            .iter()
+            .map(|twin_id| self.model.get_twin_seed(twin_id))
+            .collect();
+
+        let fut = async move {
+            let description = format!("[{model_label}] twins search");
+
+            let host_twins = retry_with_backoff(&startup_backoff, &description, || {
+                search_model_twins(&auth_builder, &twin_channel, &model_did)
+            })
+            .await;
+
+            let mut host_twins = match host_twins {
+                Ok(host_twins) => host_twins,
+                Err(e) => {
+                    error!(
+                        "[{}] skipping the twins reconciliation {:?}",
+                        &model_label, e
+                    );
+                    return;
+                }
+            };
+
+            // the registered twins deleted from the host are upserted again on the next start
+            if let Some(registry) = registry.as_ref() {
+                let removed = registry.retain_twin_dids(&host_twins);
+
+                if removed > 0 {
+                    warn!(
+                        "[{}] {} registered twins are missing on the host",
+                        &model_label, removed
+                    );
+                }
+            }
+
+            let source_twins = match auth_builder.get_identity_config() {
+                Ok(identity_config) => twin_seeds
+                    .iter()
+                    .map(|seed| {
+                        create_twin_did_with_control_delegation(
+                            &identity_config,
+                            seed,
+                            AGENT_TWIN_NAME,
+                        )
+                    })
+                    .collect::<Result<HashSet<_>, _>>(),
+                Err(e) => Err(e),
+            };
+
+            let source_twins = match source_twins {
+                Ok(source_twins) => source_twins,
                 Err(e) => {
-                    error!("[{}] failed to receive data {}", &model_label, e);
+                    error!(
+                        "[{}] skipping the twins reconciliation {:?}",
+                        &model_label, e
+                    );
+                    return;
                 }
             };
+
+            let orphans: Vec<String> = host_twins
+                .iter()
+                .filter(|twin_did| !source_twins.contains(*twin_did) && **twin_did != model_did)
+                .cloned()
+                .collect();
+
+            info!(
+                "[{}] Found {} twins on the host, {} still in the source and {} orphans",
+                &model_label,
+                host_twins.len(),
+                host_twins
+                    .iter()
+                    .filter(|twin_did| source_twins.contains(*twin_did))
+                    .count(),
+                orphans.len()
+            );
+
+            if orphans.is_empty() {
+                return;
+            }
+
+            if !delete_twins {
+                warn!(
+                    "[{}] Keeping {} orphan twins, delete_twins is not set",
+                    &model_label,
+                    orphans.len()
+                );
+                connector.on_orphan_twins(orphans).await;
+                return;
+            }
+
+            for twin_did in orphans {
+                let result = auth_builder
+                    .retry_on_auth_error(|| {
+                        delete_twin_with_channel(
+                            auth_builder.clone(),
+                            twin_channel.clone(),
+                            &twin_did,
+                        )
+                    })
+                    .await;
+
+                match result {
+                    Ok(_) => {
+                        debug!("[{}] deleted orphan twin {}", &model_label, &twin_did);
+                        ModelMetrics::increment(&metrics.twins_deleted);
+                        host_twins.remove(&twin_did);
+                    }
+                    Err(e) => error!(
+                        "[{}] failed to delete orphan twin {} {:?}",
+                        &model_label, &twin_did, e
+                    ),
+                }
+            }
+
+            if let Some(registry) = registry.as_ref() {
+                registry.retain_twin_dids(&host_twins);
+            }
         }
         .into_actor(self);
 
@@ -306,26 +914,55 @@ impl Handler<GetData> for ModelActor {
 impl Handler<TwinData> for ModelActor {
     type Result = ();
 
-    fn handle(&mut self, message: TwinData, ctx: &mut Context<Self>) -> Self::Result {
-        if SystemTime::now() > message.expire_time {
+    fn handle(&mut self, mut message: TwinData, ctx: &mut Context<Self>) -> Self::Result {
+        ModelMetrics::sub(&self.metrics.queued_twin_data, 1);
+
+        let route_span = span!(
+            parent: &message.span,
+            "route_twin_data",
+            model = %self.model.get_label(),
+            twin = %message.data.id,
+            decision = tracing::field::Empty
+        );
+        let _entered = route_span.enter();
+
+        if self.shutting_down || SystemTime::now() > message.expire_time {
             // the message is expired - drop it
+            route_span.record("decision", "expired");
             self.previously_unhandled_twins += 1;
             return;
         }
 
         let model = self.model.clone();
+        let throttling = self.settings.throttling.clone();
         let twin_seed = model.get_twin_seed(&message.data.id);
+
+        if let Some(quarantined_until) = self.quarantined_twins.get(&twin_seed) {
+            if SystemTime::now() < *quarantined_until {
+                debug!(
+                    "[{}] dropping data of quarantined twin {}",
+                    &model.get_label(),
+                    &message.data.id
+                );
+                route_span.record("decision", "quarantined");
+                return;
+            }
+
+            self.quarantined_twins.remove(&twin_seed);
+        }
+
         let twin_addr = self.twins.get(&twin_seed);
-        let twin_channel = self
-            .twin_channel
-            .as_ref()
-            .expect("this should not happen")
-            .clone();
-        let feed_channel = self
-            .feed_channel
-            .as_ref()
-            .expect("this should not happen")
-            .clone();
+
+        let (twin_channel, feed_channel) = match (&self.twin_channel, &self.feed_channel) {
+            (Some(twin_channel), Some(feed_channel)) => {
+                (twin_channel.clone(), feed_channel.clone())
+            }
+            _ => {
+                // the channels are being recreated - keep the data until they are back
+                self.reschedule(ctx, message);
+                return;
+            }
+        };
 
         let start_twin = match twin_addr {
             Some(twin) => !twin.addr.connected(),
@@ -334,9 +971,9 @@ impl Handler<TwinData> for ModelActor {
 
         if start_twin {
             // Throttle the creation of new twin actors for better host performance
-            if self.concurrent_new_twins > CONCURRENT_NEW_TWINS_LIMIT {
+            if self.concurrent_new_twins > throttling.concurrent_new_twins_limit {
                 // re-schedule the message
-                ctx.notify_later(message, RESCHEDULE_DELAY);
+                self.reschedule(ctx, message);
                 return;
             }
 
@@ -344,18 +981,18 @@ impl Handler<TwinData> for ModelActor {
 
             let twin_actor = TwinActor::new(
                 ctx.address(),
-                self.auth_builder.clone(),
                 Twin::new(
                     message.model_did.clone(),
                     twin_seed.clone(),
                     twin_label,
                     message.data.location.clone(),
-                ),
-                model,
-                twin_channel,
-                feed_channel,
-            );
+                )
+                .with_id(message.data.id.clone()),
+                self.twin_context(twin_channel, feed_channel),
+            )
+            .with_creation_span(route_span.clone());
 
+            route_span.record("decision", "created");
             let addr = twin_actor.start();
 
             self.twins.insert(
@@ -367,29 +1004,45 @@ impl Handler<TwinData> for ModelActor {
             );
 
             self.concurrent_new_twins += 1;
+            self.update_concurrency_metrics();
         };
 
-        let twin_actor = self
-            .twins
-            .get(&twin_seed)
-            .unwrap_or_else(|| panic!("[{}] this should not happen", &self.model.get_label()));
+        let twin_actor = match self.twins.get(&twin_seed) {
+            Some(twin_actor) => twin_actor,
+            None => return,
+        };
+
+        // the twin actor spans are children of the routing one
+        message.span = route_span.clone();
+
+        if !twin_actor.created {
+            // The twin actor keeps the data until the twin is created
+            if twin_actor.addr.try_send(message.clone()).is_err() {
+                self.reschedule(ctx, message);
+            }
+            return;
+        }
 
         // Throttle the sharing of data for better host performance
-        if !twin_actor.created
-            || self.concurrent_shares + message.data.feeds.len() > CONCURRENT_SHARES_LIMIT
-        {
+        if self.concurrent_shares + message.data.feeds.len() > throttling.concurrent_shares_limit {
             // re-schedule the message
-            ctx.notify_later(message, RESCHEDULE_DELAY);
+            self.reschedule(ctx, message);
             return;
         }
 
         // Share/update twin data & properties
+        route_span.record("decision", "shared");
         let feed_shares = message.data.feeds.len();
-        let result = twin_actor.addr.try_send(message);
-
-        if result.is_ok() {
-            // Increment concurrent_shares only if the message went through
-            self.concurrent_shares += feed_shares;
+        match twin_actor.addr.try_send(message) {
+            Ok(()) => {
+                // Increment concurrent_shares only if the message went through
+                self.concurrent_shares += feed_shares;
+                self.update_concurrency_metrics();
+            }
+            // the twin actor is busy, try again later
+            Err(SendError::Full(message)) => self.reschedule(ctx, message),
+            // the twin actor stopped, it is removed at the next cleanup
+            Err(SendError::Closed(_)) => self.previously_unhandled_twins += 1,
         }
     }
 }
@@ -404,7 +1057,31 @@ impl Handler<TwinConcurrencyReduction> for ModelActor {
             }
         }
 
-        self.concurrent_new_twins = cmp::max(self.concurrent_new_twins - 1, 0);
+        self.concurrent_new_twins = self.concurrent_new_twins.saturating_sub(1);
+        self.update_concurrency_metrics();
+    }
+}
+
+impl Handler<TwinQuarantined> for ModelActor {
+    type Result = ();
+
+    fn handle(&mut self, message: TwinQuarantined, _: &mut Context<Self>) -> Self::Result {
+        let quarantine_secs = self.settings.quarantine_secs;
+
+        error!(
+            "[{}] twin {} can't be created, ignoring it for {}s: {:?}",
+            &self.model.get_label(),
+            &message.twin_label,
+            quarantine_secs,
+            message.error
+        );
+
+        let quarantined_until = SystemTime::now()
+            .checked_add(Duration::from_secs(quarantine_secs))
+            .unwrap_or_else(SystemTime::now);
+
+        self.quarantined_twins
+            .insert(message.twin_seed, quarantined_until);
     }
 }
 
@@ -416,7 +1093,40 @@ impl Handler<ShareConcurrencyReduction> for ModelActor {
         message: ShareConcurrencyReduction,
         _: &mut Context<Self>,
     ) -> Self::Result {
-        self.concurrent_shares = cmp::max(self.concurrent_shares - message.shares_count, 0);
+        self.concurrent_shares = self.concurrent_shares.saturating_sub(message.shares_count);
+        self.update_concurrency_metrics();
+    }
+}
+
+impl Handler<ChannelHealthReport> for ModelActor {
+    type Result = ();
+
+    fn handle(&mut self, message: ChannelHealthReport, ctx: &mut Context<Self>) -> Self::Result {
+        if message.successes > 0 {
+            self.consecutive_transport_errors = 0;
+        }
+
+        self.consecutive_transport_errors += message.transport_errors;
+
+        if self.recreating_channels
+            || self.consecutive_transport_errors < self.settings.channel_failure_threshold
+        {
+            return;
+        }
+
+        warn!(
+            "[{}] {} consecutive transport errors, recreating the channels",
+            &self.model.get_label(),
+            self.consecutive_transport_errors
+        );
+
+        self.recreating_channels = true;
+        self.publish_state();
+        self.consecutive_transport_errors = 0;
+        self.twin_channel = None;
+        self.feed_channel = None;
+
+        self.create_channels(ctx, Some(self.channels_generation));
     }
 }
 
@@ -424,36 +1134,32 @@ impl Handler<HeartbeatData> for ModelActor {
     type Result = ();
 
     fn handle(&mut self, message: HeartbeatData, ctx: &mut Context<Self>) -> Self::Result {
-        let feed_channel = self
-            .feed_channel
-            .as_ref()
-            .expect("this should not happen")
-            .clone();
         let model_label = self.model.get_label();
 
+        let feed_channel = match self.feed_channel.as_ref() {
+            Some(feed_channel) => feed_channel.clone(),
+            None => {
+                warn!(
+                    "[{}] skipping the heartbeat, the channels are being recreated",
+                    &model_label
+                );
+                return;
+            }
+        };
+
+        let addr = ctx.address();
         let model_did = message.model_did.clone();
         let auth_builder = self.auth_builder.clone();
+        let shares = message.shares;
 
-        let data = json!({
-            "timestamp": OffsetDateTime::now_utc()
-            .format(&Rfc3339)
-            .expect("this should not happen"),
-            "shares": message.shares,
-        })
-        .to_string()
-        .as_bytes()
-        .to_vec();
+        self.last_heartbeat_shares = shares;
 
         let fut = async move {
-            let result = share_data_with_channel(
-                auth_builder,
-                feed_channel,
-                &model_did,
-                "heartbeat",
-                data,
-                true,
-            )
-            .await;
+            let result = share_heartbeat(&auth_builder, &feed_channel, &model_did, shares).await;
+
+            let mut health_report = ChannelHealthReport::default();
+            health_report.record(&result);
+            addr.do_send(health_report);
 
             if let Err(e) = result {
                 error!(
@@ -474,24 +1180,271 @@ impl Handler<Cleanup> for ModelActor {
     type Result = ();
 
     fn handle(&mut self, message: Cleanup, _ctx: &mut Context<Self>) -> Self::Result {
+        if self.shutting_down {
+            return;
+        }
+
         let model_label = self.model.get_label();
         info!("[{}] Twin cleanup", &model_label);
 
         let mut to_remove = Vec::new();
 
+        // the twins of a stream can stay quiet for long, only the stopped ones are removed
         for (twin_did, twin_actor) in &self.twins {
             if !twin_actor.addr.connected() {
                 to_remove.push(twin_did.clone());
-            } else {
-                twin_actor
-                    .addr
-                    .try_send(message.clone())
-                    .unwrap_or_else(|_| panic!("[{}] failed to send twins cleanup", &model_label));
+            } else if self.streamer.is_none() {
+                // a busy twin actor is cleaned up next time
+                if let Err(e) = twin_actor.addr.try_send(message.clone()) {
+                    debug!("[{}] skipping a twin cleanup {}", &model_label, e);
+                }
             }
         }
 
         for twin_did in to_remove.iter() {
             self.twins.remove(twin_did);
         }
+
+        let now = SystemTime::now();
+        self.quarantined_twins
+            .retain(|_, quarantined_until| now < *quarantined_until);
+
+        self.save_registry();
     }
 }
+
+impl Handler<GetStatus> for ModelActor {
+    type Result = ModelStatus;
+
+    fn handle(&mut self, _: GetStatus, _: &mut Context<Self>) -> Self::Result {
+        ModelStatus {
+            label: self.model.get_label(),
+            model_did: self.model_did.clone(),
+            state: self.state(),
+            running_twins: self
+                .twins
+                .values()
+                .filter(|twin_actor| twin_actor.addr.connected())
+                .count(),
+            quarantined_twins: self.quarantined_twins.len(),
+            concurrent_new_twins: self.concurrent_new_twins,
+            concurrent_shares: self.concurrent_shares,
+            restarts: 0,
+            last_activity: self.metrics.last_activity(),
+            live: self.metrics.is_live(),
+        }
+    }
+}
+
+impl Handler<Shutdown> for ModelActor {
+    type Result = ResponseActFuture<Self, ()>;
+
+    fn handle(&mut self, message: Shutdown, _: &mut Context<Self>) -> Self::Result {
+        let model_label = self.model.get_label();
+        info!("[{}] Shutting down", &model_label);
+
+        self.shutting_down = true;
+        self.publish_state();
+
+        let delete_twins = self.settings.delete_twins;
+        let twin_addrs: Vec<Addr<TwinActor>> = self
+            .twins
+            .values()
+            .map(|twin_actor| twin_actor.addr.clone())
+            .filter(|addr| addr.connected())
+            .collect();
+
+        for addr in &twin_addrs {
+            addr.do_send(TwinShutdown { delete_twins });
+        }
+
+        let auth_builder = self.auth_builder.clone();
+        let feed_channel = self.feed_channel.clone();
+        let model_did = self.model_did.clone();
+        let shares = self.last_heartbeat_shares;
+        let deadline = Instant::now() + message.deadline;
+
+        let fut = async move {
+            // let the twin actors finish their in-flight shares
+            while twin_addrs.iter().any(|addr| addr.connected()) {
+                if Instant::now() >= deadline {
+                    let remaining = twin_addrs.iter().filter(|addr| addr.connected()).count();
+                    warn!(
+                        "[{}] shutdown deadline reached with {} twins still sharing",
+                        &model_label, remaining
+                    );
+                    break;
+                }
+
+                sleep(SHUTDOWN_POLL_INTERVAL).await;
+            }
+
+            if let (Some(feed_channel), Some(model_did)) = (feed_channel, model_did) {
+                let result =
+                    share_heartbeat(&auth_builder, &feed_channel, &model_did, shares).await;
+
+                if let Err(e) = result {
+                    error!(
+                        "[{}] failed to share the final heartbeat {:?}",
+                        &model_label, e
+                    );
+                }
+            }
+
+            info!("[{}] Shut down", &model_label);
+        }
+        .into_actor(self)
+        .map(|_, actor, ctx| {
+            actor.save_registry();
+            ctx.stop()
+        });
+
+        Box::pin(fut)
+    }
+}
+
+/// Sends the data pushed by the streaming connector to the actor as it arrives, and a heartbeat
+/// with the number of shares every `fetch_every_secs`. The stream is reopened when it ends.
+async fn consume_stream(
+    addr: &Addr<ModelActor>,
+    streamer: Arc<dyn StreamingConnector>,
+    model_did: &str,
+    model_label: &str,
+    settings: &ModelSettings,
+    metrics: &ModelMetrics,
+) -> Result<(), anyhow::Error> {
+    let fetch_every = Duration::from_secs(settings.fetch_every_secs);
+    let expire_after = fetch_every.mul_f64(settings.throttling.new_twins_share_tick_cap);
+
+    let mut heartbeat = interval(fetch_every);
+    // the first tick completes straight away
+    heartbeat.tick().await;
+    let mut shares = 0;
+
+    loop {
+        let mut stream = retry_with_backoff(
+            &settings.stream_backoff,
+            &format!("[{model_label}] data stream opening"),
+            || streamer.stream(),
+        )
+        .await?;
+
+        info!("[{}] Data stream opened", model_label);
+
+        loop {
+            tokio::select! {
+                data = stream.next() => {
+                    let data = match data {
+                        Some(data) => data,
+                        None => break,
+                    };
+
+                    // waits for room in the mailbox instead of dropping the data
+                    ModelMetrics::add(&metrics.queued_twin_data, 1);
+                    let span = span!("stream_data", model = %model_label, twin = %data.id);
+                    addr.send(TwinData {
+                        model_did: model_did.to_string(),
+                        data,
+                        expire_time: SystemTime::now() + expire_after,
+                        span,
+                    })
+                    .await?;
+
+                    shares += 1;
+                }
+                _ = heartbeat.tick() => {
+                    if shares > 0 {
+                        metrics.record_activity();
+                    }
+
+                    addr.do_send(HeartbeatData {
+                        model_did: model_did.to_string(),
+                        shares,
+                    });
+
+                    shares = 0;
+                }
+            }
+        }
+
+        warn!("[{}] Data stream ended, reopening it", model_label);
+    }
+}
+
+/// Searches the host for the twins whose model property points at the model twin
+async fn search_model_twins(
+    auth_builder: &Arc<AuthBuilder>,
+    twin_channel: &Channel,
+    model_did: &str,
+) -> Result<HashSet<String>, anyhow::Error> {
+    let filter = SearchFilter {
+        properties: vec![PropertyBuilder::build_uri_value(
+            common_keys::predicate::MODEL_PROPERTY,
+            model_did,
+        )],
+        ..Default::default()
+    };
+
+    let mut stream = auth_builder
+        .retry_on_auth_error(|| {
+            search_with_channel(
+                auth_builder.clone(),
+                twin_channel.clone(),
+                filter.clone(),
+                Scope::Local,
+            )
+        })
+        .await?;
+
+    let mut twins = HashSet::new();
+
+    // the search stream stays open, the local results come straight away
+    while let Ok(response) = timeout(SEARCH_TIMEOUT, stream.message()).await {
+        let response = match response? {
+            Some(response) => response,
+            None => break,
+        };
+
+        for twin in response
+            .payload
+            .map(|payload| payload.twins)
+            .unwrap_or_default()
+        {
+            if let Some(twin_id) = twin.twin_id {
+                twins.insert(twin_id.id);
+            }
+        }
+    }
+
+    Ok(twins)
+}
+
+async fn share_heartbeat(
+    auth_builder: &Arc<AuthBuilder>,
+    feed_channel: &Channel,
+    model_did: &str,
+    shares: u64,
+) -> Result<(), anyhow::Error> {
+    let data = json!({
+        "timestamp": OffsetDateTime::now_utc().format(&Rfc3339)?,
+        "shares": shares,
+    })
+    .to_string()
+    .as_bytes()
+    .to_vec();
+
+    auth_builder
+        .retry_on_auth_error(|| {
+            share_data_with_channel(
+                auth_builder.clone(),
+                feed_channel.clone(),
+                model_did,
+                "heartbeat",
+                data.clone(),
+                true,
+            )
+        })
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,565 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use iotics_grpc_client::properties::common_keys;
+use iotics_grpc_client::properties::PropertyBuilder;
+use iotics_grpc_client::twin::{UpsertFeedWithMeta, UpsertInputWithMeta};
+use iotics_grpc_client::{FeedValue, LangLiteral, Literal, Property, StringLiteral, Uri, Value};
+use serde::Deserialize;
+
+use crate::config::ConfigError;
+use crate::constants::LANGUAGE;
+use crate::model::Model;
+use crate::validation::is_known_data_type;
+
+/// Model metadata loaded from a JSON, TOML or YAML file, so it can be edited without
+/// touching the connector code.
+///
+/// ```toml
+/// seed_prefix = "weather"
+/// label_prefix = "Weather"
+///
+/// [[model_properties]]
+/// key = "http://www.w3.org/2000/01/rdf-schema#label"
+/// text = "Weather Model"
+///
+/// [[feeds]]
+/// id = "weather"
+/// label = "Weather"
+///
+/// [[feeds.values]]
+/// label = "temperature"
+/// comment = "Air temperature"
+/// data_type = "decimal"
+/// unit = "http://qudt.org/vocab/unit/DEG_C"
+///
+/// [[inputs]]
+/// id = "switch"
+///
+/// [[inputs.values]]
+/// label = "on"
+/// data_type = "boolean"
+///
+/// # the label and model values are set when the twins are created
+/// [[twin_properties]]
+/// key = "http://www.w3.org/2000/01/rdf-schema#label"
+///
+/// [[twin_properties]]
+/// key = "https://data.iotics.com/app#model"
+/// ```
+///
+/// A property has a single value out of `uri`, `string`, `literal` (with a `data_type`)
+/// and `text` (with an optional `lang`, `en` by default).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelDefinition {
+    pub seed_prefix: String,
+    pub label_prefix: String,
+    #[serde(default)]
+    pub model_properties: Vec<PropertyDefinition>,
+    #[serde(default)]
+    pub feeds: Vec<FeedDefinition>,
+    #[serde(default)]
+    pub inputs: Vec<InputDefinition>,
+    #[serde(default)]
+    pub twin_properties: Vec<PropertyDefinition>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeedDefinition {
+    pub id: String,
+    /// Shortcut for a label property
+    pub label: Option<String>,
+    #[serde(default = "default_store_last")]
+    pub store_last: bool,
+    #[serde(default)]
+    pub values: Vec<FeedValueDefinition>,
+    #[serde(default)]
+    pub properties: Vec<PropertyDefinition>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InputDefinition {
+    pub id: String,
+    /// Shortcut for a label property
+    pub label: Option<String>,
+    #[serde(default)]
+    pub values: Vec<FeedValueDefinition>,
+    #[serde(default)]
+    pub properties: Vec<PropertyDefinition>,
+}
+
+/// A value of a feed or an input
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeedValueDefinition {
+    pub label: String,
+    #[serde(default)]
+    pub comment: String,
+    pub data_type: String,
+    #[serde(default)]
+    pub unit: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PropertyDefinition {
+    pub key: String,
+    pub uri: Option<String>,
+    pub string: Option<String>,
+    pub literal: Option<String>,
+    pub data_type: Option<String>,
+    pub text: Option<String>,
+    pub lang: Option<String>,
+}
+
+fn default_store_last() -> bool {
+    true
+}
+
+impl ModelDefinition {
+    /// Loads the file, picking the format from the extension (`.json`, `.toml`, `.yaml` or `.yml`)
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let file_error = |reason: String| ConfigError::File {
+            path: path.to_path_buf(),
+            reason,
+        };
+
+        let content = std::fs::read_to_string(path).map_err(|e| file_error(e.to_string()))?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&content).map_err(|e| file_error(e.to_string())),
+            Some("toml") => toml::from_str(&content).map_err(|e| file_error(e.to_string())),
+            Some("yaml") | Some("yml") => {
+                serde_yaml::from_str(&content).map_err(|e| file_error(e.to_string()))
+            }
+            _ => Err(file_error(
+                "unsupported format, expected a .json, .toml, .yaml or .yml file".to_string(),
+            )),
+        }
+    }
+
+    /// Validates the definition and builds the model. The errors point at the offending entry,
+    /// e.g. `feeds[0].values[1].data_type`.
+    pub fn into_model(self) -> Result<Model, ConfigError> {
+        required("seed_prefix", &self.seed_prefix)?;
+        required("label_prefix", &self.label_prefix)?;
+
+        let model_properties = self
+            .model_properties
+            .iter()
+            .enumerate()
+            .map(|(index, property)| property.to_property(&format!("model_properties[{index}]")))
+            .collect::<Result<_, _>>()?;
+
+        let mut feed_ids = HashSet::new();
+        let feeds = self
+            .feeds
+            .iter()
+            .enumerate()
+            .map(|(index, feed)| {
+                let key = format!("feeds[{index}]");
+
+                if !feed_ids.insert(feed.id.as_str()) {
+                    return Err(invalid(&format!("{key}.id"), "duplicate feed id"));
+                }
+
+                feed.to_feed(&key)
+            })
+            .collect::<Result<_, _>>()?;
+
+        let mut input_ids = HashSet::new();
+        let inputs = self
+            .inputs
+            .iter()
+            .enumerate()
+            .map(|(index, input)| {
+                let key = format!("inputs[{index}]");
+
+                if !input_ids.insert(input.id.as_str()) {
+                    return Err(invalid(&format!("{key}.id"), "duplicate input id"));
+                }
+
+                input.to_input(&key)
+            })
+            .collect::<Result<_, _>>()?;
+
+        let twin_properties = self
+            .twin_properties
+            .iter()
+            .enumerate()
+            .map(|(index, property)| {
+                property.to_twin_property(&format!("twin_properties[{index}]"))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Model::new(
+            self.seed_prefix,
+            self.label_prefix,
+            model_properties,
+            feeds,
+            twin_properties,
+        )
+        .with_inputs(inputs))
+    }
+}
+
+impl FeedDefinition {
+    fn to_feed(&self, key: &str) -> Result<UpsertFeedWithMeta, ConfigError> {
+        required(&format!("{key}.id"), &self.id)?;
+
+        // the model heartbeat feed is added by the engine
+        if self.id == "heartbeat" {
+            return Err(invalid(
+                &format!("{key}.id"),
+                "heartbeat is a reserved feed id",
+            ));
+        }
+
+        Ok(UpsertFeedWithMeta {
+            id: self.id.clone(),
+            store_last: self.store_last,
+            values: to_values(key, &self.values)?,
+            properties: to_properties(key, &self.properties, self.label.as_ref())?,
+        })
+    }
+}
+
+impl InputDefinition {
+    fn to_input(&self, key: &str) -> Result<UpsertInputWithMeta, ConfigError> {
+        required(&format!("{key}.id"), &self.id)?;
+
+        Ok(UpsertInputWithMeta {
+            id: self.id.clone(),
+            values: to_values(key, &self.values)?,
+            properties: to_properties(key, &self.properties, self.label.as_ref())?,
+        })
+    }
+}
+
+fn to_values(key: &str, values: &[FeedValueDefinition]) -> Result<Vec<FeedValue>, ConfigError> {
+    let mut labels = HashSet::new();
+
+    values
+        .iter()
+        .enumerate()
+        .map(|(index, value)| {
+            let key = format!("{key}.values[{index}]");
+
+            required(&format!("{key}.label"), &value.label)?;
+
+            if !labels.insert(value.label.as_str()) {
+                return Err(invalid(&format!("{key}.label"), "duplicate value label"));
+            }
+
+            if !is_known_data_type(&value.data_type) {
+                return Err(invalid(
+                    &format!("{key}.data_type"),
+                    &format!("unknown data type {}", value.data_type),
+                ));
+            }
+
+            Ok(FeedValue {
+                label: value.label.clone(),
+                comment: value.comment.clone(),
+                data_type: value.data_type.clone(),
+                unit: value.unit.clone(),
+            })
+        })
+        .collect()
+}
+
+fn to_properties(
+    key: &str,
+    properties: &[PropertyDefinition],
+    label: Option<&String>,
+) -> Result<Vec<Property>, ConfigError> {
+    let mut properties: Vec<Property> = properties
+        .iter()
+        .enumerate()
+        .map(|(index, property)| property.to_property(&format!("{key}.properties[{index}]")))
+        .collect::<Result<_, _>>()?;
+
+    if let Some(label) = label {
+        properties.push(PropertyBuilder::build_label(LANGUAGE, label));
+    }
+
+    Ok(properties)
+}
+
+impl PropertyDefinition {
+    fn to_property(&self, key: &str) -> Result<Property, ConfigError> {
+        required(&format!("{key}.key"), &self.key)?;
+
+        let value = match (&self.uri, &self.string, &self.literal, &self.text) {
+            (Some(uri), None, None, None) => Value::UriValue(Uri { value: uri.clone() }),
+            (None, Some(string), None, None) => Value::StringLiteralValue(StringLiteral {
+                value: string.clone(),
+            }),
+            (None, None, Some(literal), None) => {
+                let data_type = self.data_type.as_ref().ok_or(ConfigError::Missing {
+                    key: format!("{key}.data_type"),
+                })?;
+
+                if !is_known_data_type(data_type) {
+                    return Err(invalid(
+                        &format!("{key}.data_type"),
+                        &format!("unknown data type {data_type}"),
+                    ));
+                }
+
+                Value::LiteralValue(Literal {
+                    data_type: data_type.clone(),
+                    value: literal.clone(),
+                })
+            }
+            (None, None, None, Some(text)) => Value::LangLiteralValue(LangLiteral {
+                lang: self.lang.clone().unwrap_or_else(|| LANGUAGE.to_string()),
+                value: text.clone(),
+            }),
+            (None, None, None, None) => {
+                return Err(invalid(
+                    key,
+                    "missing value, expected one of uri, string, literal or text",
+                ))
+            }
+            _ => {
+                return Err(invalid(
+                    key,
+                    "only one of uri, string, literal or text can be set",
+                ))
+            }
+        };
+
+        Ok(Property {
+            key: self.key.clone(),
+            value: Some(value),
+        })
+    }
+
+    /// Same as `to_property` but the label and model properties can be left without a value,
+    /// it is set by `Model::build_twin_properties`
+    fn to_twin_property(&self, key: &str) -> Result<Property, ConfigError> {
+        let is_template = matches!(
+            self.key.as_str(),
+            common_keys::predicate::LABEL | common_keys::predicate::MODEL_PROPERTY
+        );
+        let has_value = self.uri.is_some()
+            || self.string.is_some()
+            || self.literal.is_some()
+            || self.text.is_some();
+
+        if is_template && !has_value {
+            return Ok(Property {
+                key: self.key.clone(),
+                value: None,
+            });
+        }
+
+        self.to_property(key)
+    }
+}
+
+impl Model {
+    /// Loads the model from a definition file, see `ModelDefinition`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+
+        ModelDefinition::load(path)?
+            .into_model()
+            .map_err(|e| ConfigError::File {
+                path: path.to_path_buf(),
+                reason: e.to_string(),
+            })
+    }
+}
+
+fn required(key: &str, value: &str) -> Result<(), ConfigError> {
+    match value.trim().is_empty() {
+        true => Err(ConfigError::Missing {
+            key: key.to_string(),
+        }),
+        false => Ok(()),
+    }
+}
+
+fn invalid(key: &str, reason: &str) -> ConfigError {
+    ConfigError::Invalid {
+        key: key.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn into_model(definition: &str) -> Result<Model, ConfigError> {
+        toml::from_str::<ModelDefinition>(definition)
+            .expect("the definition should parse")
+            .into_model()
+    }
+
+    fn invalid_key(definition: &str) -> String {
+        match into_model(definition) {
+            Err(ConfigError::Invalid { key, .. }) | Err(ConfigError::Missing { key }) => key,
+            result => panic!("expected the definition to be invalid, got {result:?}"),
+        }
+    }
+
+    const PREFIXES: &str = r#"
+        seed_prefix = "weather"
+        label_prefix = "Weather"
+    "#;
+
+    #[test]
+    fn builds_the_model() {
+        let model = into_model(&format!(
+            r#"{PREFIXES}
+            [[model_properties]]
+            key = "http://www.w3.org/2000/01/rdf-schema#comment"
+            text = "Weather stations"
+
+            [[feeds]]
+            id = "weather"
+            label = "Weather"
+
+            [[feeds.values]]
+            label = "temperature"
+            data_type = "xsd:decimal"
+
+            [[twin_properties]]
+            key = "http://www.w3.org/2000/01/rdf-schema#label"
+            "#
+        ))
+        .expect("the definition should be valid");
+
+        assert_eq!(model.get_seed(), "weather Model");
+        assert_eq!(model.get_model_properties().len(), 1);
+        assert_eq!(
+            model.get_feed("weather").map(|feed| feed.values.len()),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn requires_the_prefixes() {
+        assert_eq!(
+            invalid_key(
+                r#"seed_prefix = " "
+            label_prefix = "Weather""#
+            ),
+            "seed_prefix"
+        );
+    }
+
+    #[test]
+    fn rejects_the_duplicate_ids() {
+        let feeds = r#"
+            [[feeds]]
+            id = "weather"
+
+            [[feeds]]
+            id = "weather"
+        "#;
+        let inputs = r#"
+            [[inputs]]
+            id = "switch"
+
+            [[inputs]]
+            id = "switch"
+        "#;
+
+        assert_eq!(invalid_key(&format!("{PREFIXES}{feeds}")), "feeds[1].id");
+        assert_eq!(invalid_key(&format!("{PREFIXES}{inputs}")), "inputs[1].id");
+    }
+
+    #[test]
+    fn rejects_the_heartbeat_feed() {
+        let feeds = r#"
+            [[feeds]]
+            id = "heartbeat"
+        "#;
+
+        assert_eq!(invalid_key(&format!("{PREFIXES}{feeds}")), "feeds[0].id");
+    }
+
+    #[test]
+    fn rejects_the_invalid_values() {
+        let duplicate = r#"
+            [[feeds]]
+            id = "weather"
+
+            [[feeds.values]]
+            label = "temperature"
+            data_type = "decimal"
+
+            [[feeds.values]]
+            label = "temperature"
+            data_type = "decimal"
+        "#;
+        let unknown_data_type = r#"
+            [[feeds]]
+            id = "weather"
+
+            [[feeds.values]]
+            label = "temperature"
+            data_type = "celsius"
+        "#;
+
+        assert_eq!(
+            invalid_key(&format!("{PREFIXES}{duplicate}")),
+            "feeds[0].values[1].label"
+        );
+        assert_eq!(
+            invalid_key(&format!("{PREFIXES}{unknown_data_type}")),
+            "feeds[0].values[0].data_type"
+        );
+    }
+
+    #[test]
+    fn rejects_the_invalid_properties() {
+        let no_value = r#"
+            [[model_properties]]
+            key = "http://www.w3.org/2000/01/rdf-schema#comment"
+        "#;
+        let two_values = r#"
+            [[model_properties]]
+            key = "http://www.w3.org/2000/01/rdf-schema#comment"
+            text = "Weather stations"
+            string = "Weather stations"
+        "#;
+        let no_data_type = r#"
+            [[feeds]]
+            id = "weather"
+
+            [[feeds.properties]]
+            key = "http://schema.org/elevation"
+            literal = "12"
+        "#;
+        let no_twin_value = r#"
+            [[twin_properties]]
+            key = "http://www.w3.org/2000/01/rdf-schema#comment"
+        "#;
+
+        assert_eq!(
+            invalid_key(&format!("{PREFIXES}{no_value}")),
+            "model_properties[0]"
+        );
+        assert_eq!(
+            invalid_key(&format!("{PREFIXES}{two_values}")),
+            "model_properties[0]"
+        );
+        assert_eq!(
+            invalid_key(&format!("{PREFIXES}{no_data_type}")),
+            "feeds[0].properties[0].data_type"
+        );
+        assert_eq!(
+            invalid_key(&format!("{PREFIXES}{no_twin_value}")),
+            "twin_properties[0]"
+        );
+    }
+}
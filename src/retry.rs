@@ -0,0 +1,169 @@
+use std::future::Future;
+use std::io;
+
+use actix::clock::sleep;
+use log::{debug, error, warn};
+use tonic::{Code, Streaming};
+
+use crate::settings::BackoffSettings;
+
+/// Runs the operation returned by `f` until it succeeds or the backoff gives up.
+/// The returned error is the last one, with the number of attempts as context.
+pub async fn retry_with_backoff<T, F, Fut>(
+    backoff: &BackoffSettings,
+    description: &str,
+    f: F,
+) -> Result<T, anyhow::Error>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, anyhow::Error>>,
+{
+    retry_with_backoff_if(backoff, description, |_| true, f).await
+}
+
+/// Same as `retry_with_backoff` but gives up straight away on the errors for which
+/// `is_retryable` returns false
+pub async fn retry_with_backoff_if<T, F, Fut, R>(
+    backoff: &BackoffSettings,
+    description: &str,
+    is_retryable: R,
+    f: F,
+) -> Result<T, anyhow::Error>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, anyhow::Error>>,
+    R: Fn(&anyhow::Error) -> bool,
+{
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+
+        match f().await {
+            Ok(value) => return Ok(value),
+            Err(e) if is_retryable(&e) && backoff.can_retry(attempt) => {
+                let delay = backoff.delay(attempt);
+                warn!(
+                    "{} failed (attempt {}), retrying in {:?}: {:?}",
+                    description, attempt, delay, e
+                );
+
+                sleep(delay).await;
+            }
+            Err(e) => {
+                return Err(e.context(format!("{description} failed after {attempt} attempts")));
+            }
+        }
+    }
+}
+
+/// Keeps the gRPC stream opened by `subscribe` open and passes its messages to `on_message`.
+/// The stream is reopened with the backoff when it fails or ends, the attempts are reset once it
+/// is open again. `on_subscribe` gets the result of each opening, e.g. to report the channel health.
+pub(crate) async fn resubscribe_with_backoff<M, F, Fut, S, H, HFut>(
+    backoff: &BackoffSettings,
+    description: &str,
+    subscribe: F,
+    on_subscribe: S,
+    on_message: H,
+) where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<Streaming<M>, anyhow::Error>>,
+    S: Fn(&Result<Streaming<M>, anyhow::Error>),
+    H: Fn(M) -> HFut,
+    HFut: Future<Output = ()>,
+{
+    let mut attempt = 0;
+
+    loop {
+        let result = subscribe().await;
+        on_subscribe(&result);
+
+        match result {
+            Ok(mut stream) => {
+                debug!("{} opened", description);
+                attempt = 0;
+
+                loop {
+                    match stream.message().await {
+                        Ok(Some(message)) => on_message(message).await,
+                        Ok(None) => break,
+                        Err(e) => {
+                            warn!("{} failed {:?}", description, e);
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(e) => error!("{} couldn't be opened {:?}", description, e),
+        }
+
+        attempt += 1;
+
+        if !backoff.can_retry(attempt) {
+            error!("{} given up after {} attempts", description, attempt);
+            return;
+        }
+
+        sleep(backoff.delay(attempt)).await;
+    }
+}
+
+/// The gRPC status the call failed with, if any
+pub(crate) fn grpc_status(error: &anyhow::Error) -> Option<&tonic::Status> {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<tonic::Status>())
+}
+
+/// Checks whether a gRPC call failed because of the connection rather than the request itself
+pub(crate) fn is_transport_error(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(status) = cause.downcast_ref::<tonic::Status>() {
+            return status.code() == Code::Unavailable;
+        }
+
+        cause.is::<tonic::transport::Error>()
+            || cause.downcast_ref::<io::Error>().is_some_and(|e| {
+                matches!(
+                    e.kind(),
+                    io::ErrorKind::BrokenPipe
+                        | io::ErrorKind::ConnectionAborted
+                        | io::ErrorKind::ConnectionRefused
+                        | io::ErrorKind::ConnectionReset
+                        | io::ErrorKind::TimedOut
+                )
+            })
+    })
+}
+
+/// Checks whether the host rejected the request itself, it would fail again however many times
+/// it is retried. The errors which can't be identified are not permanent.
+pub(crate) fn is_permanent_error(error: &anyhow::Error) -> bool {
+    grpc_status(error).is_some_and(|status| {
+        matches!(
+            status.code(),
+            Code::InvalidArgument | Code::FailedPrecondition
+        )
+    })
+}
+
+/// Checks whether the host doesn't know the twin or feed of the request
+pub(crate) fn is_not_found_error(error: &anyhow::Error) -> bool {
+    grpc_status(error).is_some_and(|status| status.code() == Code::NotFound)
+}
+
+/// Checks whether a failed gRPC call is worth retrying, as opposed to the permanent errors
+/// (invalid argument, not found, permission denied, ...) which would fail again
+pub(crate) fn is_retryable_error(error: &anyhow::Error) -> bool {
+    if is_transport_error(error) {
+        return true;
+    }
+
+    grpc_status(error).is_some_and(|status| {
+        matches!(
+            status.code(),
+            Code::DeadlineExceeded | Code::ResourceExhausted | Code::Aborted
+        )
+    })
+}
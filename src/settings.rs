@@ -0,0 +1,800 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::LevelFilter;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::config::{parse_value, read_env, ConfigError, EngineConfig, EngineConfigBuilder};
+use crate::constants::{
+    BACKOFF_INITIAL_DELAY, BACKOFF_JITTER, BACKOFF_MAX_ATTEMPTS, BACKOFF_MAX_DELAY,
+    BACKOFF_MULTIPLIER, CHANNEL_FAILURE_THRESHOLD, CLEANUP_INTERVAL_MULTIPLIER,
+    CONCURRENT_NEW_TWINS_LIMIT, CONCURRENT_SHARES_LIMIT, DEFAULT_FETCH_EVERY_SECS,
+    LIVENESS_MULTIPLIER, NEW_TWINS_SHARE_TICK_CAP, QUARANTINE_DURATION, RESCHEDULE_DELAY,
+    SHARE_BACKOFF_INITIAL_DELAY, SHARE_BACKOFF_MAX_DELAY, SHARE_MAX_ATTEMPTS,
+    TWIN_CREATION_BACKOFF_MAX_DELAY, TWIN_CREATION_MAX_ATTEMPTS,
+};
+
+/// Per model settings
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModelSettings {
+    /// How often `Connector::get_data` is called
+    pub fetch_every_secs: u64,
+    /// Delete the twins which didn't receive data for a cleanup interval instead of just stopping their actors
+    /// (the twins of a streaming model are never expired)
+    pub delete_twins: bool,
+    /// The cleanup interval as a multiple of `fetch_every_secs`
+    pub cleanup_interval_multiplier: f64,
+    /// The model fails the liveness check when nothing was fetched or shared for this multiple of `fetch_every_secs`
+    pub liveness_multiplier: f64,
+    pub throttling: ThrottlingSettings,
+    /// Retry policy of the model twin creation
+    pub startup_backoff: BackoffSettings,
+    /// Retry policy of the gRPC channels creation, retries forever by default
+    pub channel_backoff: BackoffSettings,
+    /// Number of consecutive transport errors after which the channels are recreated
+    pub channel_failure_threshold: usize,
+    /// Retry policy of the data stream reopening of the streaming connectors, retries forever by default
+    pub stream_backoff: BackoffSettings,
+    /// Retry policy of the feed shares, only the transient errors are retried
+    pub share_backoff: BackoffSettings,
+    /// Retry policy of the twins creation, every error is retried but the ones rejecting the twin
+    /// itself (invalid argument, failed precondition), which quarantine it
+    pub twin_creation_backoff: BackoffSettings,
+    /// How long the data of a twin which can't be created (e.g. invalid properties) is ignored
+    pub quarantine_secs: u64,
+    /// Check the feed values against the `FeedValue`s of the model before sharing them,
+    /// the values which don't match are reported to `Connector::on_invalid_value` and dropped.
+    /// Off by default, the values are shared as they are.
+    pub validate_feed_values: bool,
+    /// Search the host for the twins of the model after the first fetch, the ones which aren't
+    /// in the source anymore are deleted if `delete_twins` is set or reported to `Connector::on_orphan_twins`.
+    /// The connector must return all its twins from the first fetch for this to work, so it is off by default.
+    pub reconcile_twins: bool,
+    /// File of the twin registry, the twins whose metadata didn't change since they were last
+    /// upserted are adopted on startup without an upsert. One file per model, disabled by default.
+    pub registry_path: Option<PathBuf>,
+    /// Remote feeds followed by the model twin, their values are passed to `Connector::on_followed_data`
+    pub follow: Vec<FollowedFeed>,
+}
+
+impl Default for ModelSettings {
+    fn default() -> Self {
+        Self {
+            fetch_every_secs: DEFAULT_FETCH_EVERY_SECS,
+            delete_twins: false,
+            cleanup_interval_multiplier: CLEANUP_INTERVAL_MULTIPLIER,
+            liveness_multiplier: LIVENESS_MULTIPLIER,
+            throttling: ThrottlingSettings::default(),
+            startup_backoff: BackoffSettings::default(),
+            channel_backoff: BackoffSettings {
+                max_attempts: None,
+                ..Default::default()
+            },
+            channel_failure_threshold: CHANNEL_FAILURE_THRESHOLD,
+            stream_backoff: BackoffSettings {
+                max_attempts: None,
+                ..Default::default()
+            },
+            share_backoff: BackoffSettings {
+                initial_delay_ms: SHARE_BACKOFF_INITIAL_DELAY.as_millis() as u64,
+                max_delay_ms: SHARE_BACKOFF_MAX_DELAY.as_millis() as u64,
+                max_attempts: Some(SHARE_MAX_ATTEMPTS),
+                ..Default::default()
+            },
+            twin_creation_backoff: BackoffSettings {
+                max_delay_ms: TWIN_CREATION_BACKOFF_MAX_DELAY.as_millis() as u64,
+                max_attempts: Some(TWIN_CREATION_MAX_ATTEMPTS),
+                ..Default::default()
+            },
+            quarantine_secs: QUARANTINE_DURATION.as_secs(),
+            validate_feed_values: false,
+            reconcile_twins: false,
+            registry_path: None,
+            follow: Vec::new(),
+        }
+    }
+}
+
+impl ModelSettings {
+    pub fn new(fetch_every_secs: u64, delete_twins: bool) -> Self {
+        Self {
+            fetch_every_secs,
+            delete_twins,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_throttling(mut self, throttling: ThrottlingSettings) -> Self {
+        self.throttling = throttling;
+        self
+    }
+
+    pub fn with_followed_feeds(mut self, follow: Vec<FollowedFeed>) -> Self {
+        self.follow = follow;
+        self
+    }
+
+    fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        if self.fetch_every_secs == 0 {
+            return Err(ConfigError::Invalid {
+                key: format!("{key}.fetch_every_secs"),
+                reason: "must be greater than 0".to_string(),
+            });
+        }
+
+        if self.cleanup_interval_multiplier < 1.0 {
+            return Err(ConfigError::Invalid {
+                key: format!("{key}.cleanup_interval_multiplier"),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+
+        if self.liveness_multiplier < 1.0 {
+            return Err(ConfigError::Invalid {
+                key: format!("{key}.liveness_multiplier"),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+
+        if self.channel_failure_threshold == 0 {
+            return Err(ConfigError::Invalid {
+                key: format!("{key}.channel_failure_threshold"),
+                reason: "must be greater than 0".to_string(),
+            });
+        }
+
+        for (index, followed) in self.follow.iter().enumerate() {
+            followed.validate(&format!("{key}.follow[{index}]"))?;
+        }
+
+        self.throttling.validate(&format!("{key}.throttling"))?;
+        self.startup_backoff
+            .validate(&format!("{key}.startup_backoff"))?;
+        self.channel_backoff
+            .validate(&format!("{key}.channel_backoff"))?;
+        self.stream_backoff
+            .validate(&format!("{key}.stream_backoff"))?;
+        self.share_backoff
+            .validate(&format!("{key}.share_backoff"))?;
+        self.twin_creation_backoff
+            .validate(&format!("{key}.twin_creation_backoff"))
+    }
+
+    /// Overrides the settings with the `IOTICS_MODELS_<NAME>_*` environment variables which are set
+    fn with_env_overrides(mut self, name: &str) -> Result<Self, ConfigError> {
+        let prefix = format!("IOTICS_MODELS_{}", env_name(name));
+
+        let key = format!("{prefix}_FETCH_EVERY_SECS");
+        if let Some(value) = read_env(&key) {
+            self.fetch_every_secs = parse_value(&key, &value)?;
+        }
+
+        let key = format!("{prefix}_DELETE_TWINS");
+        if let Some(value) = read_env(&key) {
+            self.delete_twins = parse_value(&key, &value)?;
+        }
+
+        let key = format!("{prefix}_CLEANUP_INTERVAL_MULTIPLIER");
+        if let Some(value) = read_env(&key) {
+            self.cleanup_interval_multiplier = parse_value(&key, &value)?;
+        }
+
+        let key = format!("{prefix}_LIVENESS_MULTIPLIER");
+        if let Some(value) = read_env(&key) {
+            self.liveness_multiplier = parse_value(&key, &value)?;
+        }
+
+        self.throttling = self.throttling.with_env_overrides(&prefix)?;
+        self.startup_backoff = self
+            .startup_backoff
+            .with_env_overrides(&format!("{prefix}_STARTUP_BACKOFF"))?;
+
+        let key = format!("{prefix}_CHANNEL_FAILURE_THRESHOLD");
+        if let Some(value) = read_env(&key) {
+            self.channel_failure_threshold = parse_value(&key, &value)?;
+        }
+
+        self.channel_backoff = self
+            .channel_backoff
+            .with_env_overrides(&format!("{prefix}_CHANNEL_BACKOFF"))?;
+        self.stream_backoff = self
+            .stream_backoff
+            .with_env_overrides(&format!("{prefix}_STREAM_BACKOFF"))?;
+        self.share_backoff = self
+            .share_backoff
+            .with_env_overrides(&format!("{prefix}_SHARE_BACKOFF"))?;
+        self.twin_creation_backoff = self
+            .twin_creation_backoff
+            .with_env_overrides(&format!("{prefix}_TWIN_CREATION_BACKOFF"))?;
+
+        let key = format!("{prefix}_QUARANTINE_SECS");
+        if let Some(value) = read_env(&key) {
+            self.quarantine_secs = parse_value(&key, &value)?;
+        }
+
+        let key = format!("{prefix}_RECONCILE_TWINS");
+        if let Some(value) = read_env(&key) {
+            self.reconcile_twins = parse_value(&key, &value)?;
+        }
+
+        let key = format!("{prefix}_REGISTRY_PATH");
+        if let Some(value) = read_env(&key) {
+            self.registry_path = Some(parse_value(&key, &value)?);
+        }
+
+        let key = format!("{prefix}_VALIDATE_FEED_VALUES");
+        if let Some(value) = read_env(&key) {
+            self.validate_feed_values = parse_value(&key, &value)?;
+        }
+
+        Ok(self)
+    }
+}
+
+/// A feed of a twin, possibly of another agent, followed by the model twin
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FollowedFeed {
+    pub twin_did: String,
+    pub feed_id: String,
+}
+
+impl FollowedFeed {
+    pub fn new(twin_did: &str, feed_id: &str) -> Self {
+        Self {
+            twin_did: twin_did.to_string(),
+            feed_id: feed_id.to_string(),
+        }
+    }
+
+    fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        if !self.twin_did.starts_with("did:iotics:") {
+            return Err(ConfigError::Invalid {
+                key: format!("{key}.twin_did"),
+                reason: "must start with did:iotics:".to_string(),
+            });
+        }
+
+        if self.feed_id.trim().is_empty() {
+            return Err(ConfigError::Missing {
+                key: format!("{key}.feed_id"),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Limits used by the `ModelActor` to avoid overloading the host
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThrottlingSettings {
+    /// Maximum number of twins being created at the same time
+    pub concurrent_new_twins_limit: usize,
+    /// Maximum number of feed shares in flight at the same time
+    pub concurrent_shares_limit: usize,
+    /// Fraction of `fetch_every_secs` during which the fetched data can be shared,
+    /// the data which couldn't be handled in time is dropped
+    pub new_twins_share_tick_cap: f64,
+    /// Delay before re-trying to handle data which was throttled
+    pub reschedule_delay_ms: u64,
+}
+
+impl Default for ThrottlingSettings {
+    fn default() -> Self {
+        Self {
+            concurrent_new_twins_limit: CONCURRENT_NEW_TWINS_LIMIT,
+            concurrent_shares_limit: CONCURRENT_SHARES_LIMIT,
+            new_twins_share_tick_cap: NEW_TWINS_SHARE_TICK_CAP,
+            reschedule_delay_ms: RESCHEDULE_DELAY.as_millis() as u64,
+        }
+    }
+}
+
+impl ThrottlingSettings {
+    pub fn reschedule_delay(&self) -> Duration {
+        Duration::from_millis(self.reschedule_delay_ms)
+    }
+
+    fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        if self.concurrent_new_twins_limit == 0 {
+            return Err(ConfigError::Invalid {
+                key: format!("{key}.concurrent_new_twins_limit"),
+                reason: "must be greater than 0".to_string(),
+            });
+        }
+
+        if self.concurrent_shares_limit == 0 {
+            return Err(ConfigError::Invalid {
+                key: format!("{key}.concurrent_shares_limit"),
+                reason: "must be greater than 0".to_string(),
+            });
+        }
+
+        if self.new_twins_share_tick_cap <= 0.0 || self.new_twins_share_tick_cap > 1.0 {
+            return Err(ConfigError::Invalid {
+                key: format!("{key}.new_twins_share_tick_cap"),
+                reason: "must be greater than 0 and at most 1".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn with_env_overrides(mut self, prefix: &str) -> Result<Self, ConfigError> {
+        let key = format!("{prefix}_CONCURRENT_NEW_TWINS_LIMIT");
+        if let Some(value) = read_env(&key) {
+            self.concurrent_new_twins_limit = parse_value(&key, &value)?;
+        }
+
+        let key = format!("{prefix}_CONCURRENT_SHARES_LIMIT");
+        if let Some(value) = read_env(&key) {
+            self.concurrent_shares_limit = parse_value(&key, &value)?;
+        }
+
+        let key = format!("{prefix}_NEW_TWINS_SHARE_TICK_CAP");
+        if let Some(value) = read_env(&key) {
+            self.new_twins_share_tick_cap = parse_value(&key, &value)?;
+        }
+
+        let key = format!("{prefix}_RESCHEDULE_DELAY_MS");
+        if let Some(value) = read_env(&key) {
+            self.reschedule_delay_ms = parse_value(&key, &value)?;
+        }
+
+        Ok(self)
+    }
+}
+
+/// Exponential backoff used when retrying an operation
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackoffSettings {
+    /// Delay before the first retry
+    pub initial_delay_ms: u64,
+    /// Upper bound of the delay between two attempts
+    pub max_delay_ms: u64,
+    /// Factor applied to the delay after each attempt
+    pub multiplier: f64,
+    /// Fraction of the delay randomly added or removed, between 0 and 1
+    pub jitter: f64,
+    /// Maximum number of attempts, including the first one. Retries forever if `None`.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for BackoffSettings {
+    fn default() -> Self {
+        Self {
+            initial_delay_ms: BACKOFF_INITIAL_DELAY.as_millis() as u64,
+            max_delay_ms: BACKOFF_MAX_DELAY.as_millis() as u64,
+            multiplier: BACKOFF_MULTIPLIER,
+            jitter: BACKOFF_JITTER,
+            max_attempts: Some(BACKOFF_MAX_ATTEMPTS),
+        }
+    }
+}
+
+impl BackoffSettings {
+    /// The delay to wait after the given failed attempt (starting at 1)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = (self.initial_delay_ms as f64 * self.multiplier.powi(exponent))
+            .min(self.max_delay_ms as f64);
+
+        let jitter = match self.jitter > 0.0 {
+            true => rand::thread_rng().gen_range(-self.jitter..=self.jitter),
+            false => 0.0,
+        };
+
+        Duration::from_millis((delay * (1.0 + jitter)).max(0.0) as u64)
+    }
+
+    /// Whether another attempt is allowed after the given failed attempt (starting at 1)
+    pub fn can_retry(&self, attempt: u32) -> bool {
+        match self.max_attempts {
+            Some(max_attempts) => attempt < max_attempts,
+            None => true,
+        }
+    }
+
+    fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        if self.max_delay_ms == 0 {
+            return Err(ConfigError::Invalid {
+                key: format!("{key}.max_delay_ms"),
+                reason: "must be greater than 0".to_string(),
+            });
+        }
+
+        if self.initial_delay_ms > self.max_delay_ms {
+            return Err(ConfigError::Invalid {
+                key: format!("{key}.initial_delay_ms"),
+                reason: "must be at most max_delay_ms".to_string(),
+            });
+        }
+
+        if self.multiplier < 1.0 {
+            return Err(ConfigError::Invalid {
+                key: format!("{key}.multiplier"),
+                reason: "must be at least 1".to_string(),
+            });
+        }
+
+        if !(0.0..=1.0).contains(&self.jitter) {
+            return Err(ConfigError::Invalid {
+                key: format!("{key}.jitter"),
+                reason: "must be between 0 and 1".to_string(),
+            });
+        }
+
+        if self.max_attempts == Some(0) {
+            return Err(ConfigError::Invalid {
+                key: format!("{key}.max_attempts"),
+                reason: "must be greater than 0".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn with_env_overrides(mut self, prefix: &str) -> Result<Self, ConfigError> {
+        let key = format!("{prefix}_INITIAL_DELAY_MS");
+        if let Some(value) = read_env(&key) {
+            self.initial_delay_ms = parse_value(&key, &value)?;
+        }
+
+        let key = format!("{prefix}_MAX_DELAY_MS");
+        if let Some(value) = read_env(&key) {
+            self.max_delay_ms = parse_value(&key, &value)?;
+        }
+
+        let key = format!("{prefix}_MULTIPLIER");
+        if let Some(value) = read_env(&key) {
+            self.multiplier = parse_value(&key, &value)?;
+        }
+
+        let key = format!("{prefix}_JITTER");
+        if let Some(value) = read_env(&key) {
+            self.jitter = parse_value(&key, &value)?;
+        }
+
+        // an empty value or `none` retries forever
+        let key = format!("{prefix}_MAX_ATTEMPTS");
+        if let Ok(value) = std::env::var(&key) {
+            self.max_attempts = match value.trim() {
+                "" => None,
+                value if value.eq_ignore_ascii_case("none") => None,
+                value => Some(parse_value(&key, value)?),
+            };
+        }
+
+        Ok(self)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingSettings {
+    /// Maximum log level, one of `off`, `error`, `warn`, `info`, `debug` or `trace`
+    pub level: Option<String>,
+}
+
+impl LoggingSettings {
+    pub fn level_filter(&self) -> Result<Option<LevelFilter>, ConfigError> {
+        self.level
+            .as_ref()
+            .map(|level| parse_value("logging.level", level))
+            .transpose()
+    }
+
+    /// Caps the log level of the `log` facade.
+    /// The logger implementation itself is still up to the connector.
+    pub fn apply(&self) -> Result<(), ConfigError> {
+        if let Some(level_filter) = self.level_filter()? {
+            log::set_max_level(level_filter);
+        }
+
+        Ok(())
+    }
+}
+
+/// Connector configuration loaded from a TOML or YAML file.
+///
+/// ```toml
+/// [host]
+/// address = "https://my-host.iotics.space"
+///
+/// [identity]
+/// resolver_address = "https://did.stg.iotics.com"
+/// user_did = "did:iotics:..."
+/// agent_did = "did:iotics:..."
+/// agent_name = "my-agent"
+/// agent_secret = "..."
+/// token_duration = 3600
+///
+/// [logging]
+/// level = "info"
+///
+/// [models.weather]
+/// fetch_every_secs = 60
+/// delete_twins = true
+///
+/// [models.weather.throttling]
+/// concurrent_new_twins_limit = 8
+/// concurrent_shares_limit = 256
+/// ```
+///
+/// Every value can be overridden by an environment variable: the `IOTICS_*` variables
+/// for the host and identity, `IOTICS_LOG_LEVEL` for logging and
+/// `IOTICS_MODELS_<NAME>_<SETTING>` (e.g. `IOTICS_MODELS_WEATHER_FETCH_EVERY_SECS`) for the models.
+#[derive(Debug, Clone)]
+pub struct ConnectorConfig {
+    pub engine: EngineConfig,
+    pub logging: LoggingSettings,
+    pub models: HashMap<String, ModelSettings>,
+}
+
+impl ConnectorConfig {
+    /// Loads the file, picking the format from the extension (`.toml`, `.yaml` or `.yml`),
+    /// and applies the environment variable overrides
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let file_error = |reason: String| ConfigError::File {
+            path: path.to_path_buf(),
+            reason,
+        };
+
+        let content = std::fs::read_to_string(path).map_err(|e| file_error(e.to_string()))?;
+
+        let file: FileConfig = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&content).map_err(|e| file_error(e.to_string()))?,
+            Some("yaml") | Some("yml") => {
+                serde_yaml::from_str(&content).map_err(|e| file_error(e.to_string()))?
+            }
+            _ => {
+                return Err(file_error(
+                    "unsupported format, expected a .toml, .yaml or .yml file".to_string(),
+                ))
+            }
+        };
+
+        dotenv::dotenv().ok();
+
+        file.into_config()
+    }
+
+    /// Returns the settings of the model with the given name or the default settings
+    /// (with the environment variable overrides) if the file doesn't declare it
+    pub fn model(&self, name: &str) -> Result<ModelSettings, ConfigError> {
+        match self.models.get(name) {
+            Some(settings) => Ok(settings.clone()),
+            None => {
+                let settings = ModelSettings::default().with_env_overrides(name)?;
+                settings.validate(&format!("models.{name}"))?;
+
+                Ok(settings)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    host: HostSection,
+    identity: IdentitySection,
+    logging: LoggingSettings,
+    models: HashMap<String, ModelSettings>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct HostSection {
+    address: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct IdentitySection {
+    resolver_address: Option<String>,
+    user_did: Option<String>,
+    agent_did: Option<String>,
+    agent_name: Option<String>,
+    agent_secret: Option<String>,
+    token_duration: Option<i64>,
+    /// In seconds
+    token_renewal_margin: Option<u64>,
+}
+
+impl FileConfig {
+    fn into_config(self) -> Result<ConnectorConfig, ConfigError> {
+        let mut builder = EngineConfigBuilder::default();
+
+        if let Some(address) = self.host.address {
+            builder = builder.host_address(address);
+        }
+
+        let identity = self.identity;
+
+        if let Some(resolver_address) = identity.resolver_address {
+            builder = builder.resolver_address(resolver_address);
+        }
+        if let Some(user_did) = identity.user_did {
+            builder = builder.user_did(user_did);
+        }
+        if let Some(agent_did) = identity.agent_did {
+            builder = builder.agent_did(agent_did);
+        }
+        if let Some(agent_name) = identity.agent_name {
+            builder = builder.agent_name(agent_name);
+        }
+        if let Some(agent_secret) = identity.agent_secret {
+            builder = builder.agent_secret(agent_secret);
+        }
+        if let Some(token_duration) = identity.token_duration {
+            builder = builder.token_duration(token_duration);
+        }
+        if let Some(margin) = identity.token_renewal_margin {
+            builder = builder.token_renewal_margin(Duration::from_secs(margin));
+        }
+
+        let engine = builder.with_env_overrides()?.build()?;
+
+        let mut logging = self.logging;
+
+        if let Some(level) = read_env("IOTICS_LOG_LEVEL") {
+            logging.level.replace(level);
+        }
+
+        logging.level_filter()?;
+
+        let models = self
+            .models
+            .into_iter()
+            .map(|(name, settings)| {
+                let settings = settings.with_env_overrides(&name)?;
+                settings.validate(&format!("models.{name}"))?;
+
+                Ok((name, settings))
+            })
+            .collect::<Result<HashMap<_, _>, ConfigError>>()?;
+
+        Ok(ConnectorConfig {
+            engine,
+            logging,
+            models,
+        })
+    }
+}
+
+/// Converts a model name to the form used in the environment variable names
+fn env_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_uppercase(),
+            false => '_',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backoff() -> BackoffSettings {
+        BackoffSettings {
+            initial_delay_ms: 100,
+            max_delay_ms: 1_000,
+            multiplier: 2.0,
+            jitter: 0.0,
+            max_attempts: Some(3),
+        }
+    }
+
+    #[test]
+    fn backoff_delay_grows_up_to_the_max() {
+        let backoff = backoff();
+
+        assert_eq!(backoff.delay(1), Duration::from_millis(100));
+        assert_eq!(backoff.delay(2), Duration::from_millis(200));
+        assert_eq!(backoff.delay(4), Duration::from_millis(800));
+        assert_eq!(backoff.delay(5), Duration::from_millis(1_000));
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_millis(1_000));
+    }
+
+    #[test]
+    fn backoff_delay_jitter() {
+        let backoff = BackoffSettings {
+            jitter: 0.5,
+            ..backoff()
+        };
+
+        for _ in 0..100 {
+            let delay = backoff.delay(2);
+
+            assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(300));
+        }
+    }
+
+    #[test]
+    fn backoff_attempts() {
+        assert!(backoff().can_retry(2));
+        assert!(!backoff().can_retry(3));
+        assert!(BackoffSettings {
+            max_attempts: None,
+            ..backoff()
+        }
+        .can_retry(u32::MAX));
+    }
+
+    #[test]
+    fn backoff_validation() {
+        assert_eq!(backoff().validate("backoff"), Ok(()));
+
+        let invalid = [
+            (
+                BackoffSettings {
+                    max_delay_ms: 0,
+                    ..backoff()
+                },
+                "backoff.max_delay_ms",
+            ),
+            (
+                BackoffSettings {
+                    initial_delay_ms: 2000,
+                    ..backoff()
+                },
+                "backoff.initial_delay_ms",
+            ),
+            (
+                BackoffSettings {
+                    multiplier: 0.5,
+                    ..backoff()
+                },
+                "backoff.multiplier",
+            ),
+            (
+                BackoffSettings {
+                    jitter: 1.5,
+                    ..backoff()
+                },
+                "backoff.jitter",
+            ),
+            (
+                BackoffSettings {
+                    max_attempts: Some(0),
+                    ..backoff()
+                },
+                "backoff.max_attempts",
+            ),
+        ];
+
+        for (backoff, key) in invalid {
+            match backoff.validate("backoff") {
+                Err(ConfigError::Invalid {
+                    key: invalid_key, ..
+                }) => assert_eq!(invalid_key, key),
+                result => panic!("expected {key} to be invalid, got {result:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn backoff_max_attempts_override() {
+        let prefix = "IOTICS_TEST_BACKOFF";
+        let key = format!("{prefix}_MAX_ATTEMPTS");
+
+        for (value, max_attempts) in [("5", Some(5)), ("", None), ("none", None), ("None", None)] {
+            std::env::set_var(&key, value);
+            let backoff = backoff().with_env_overrides(prefix).unwrap();
+            assert_eq!(backoff.max_attempts, max_attempts, "{value:?}");
+        }
+
+        std::env::set_var(&key, "many");
+        assert!(backoff().with_env_overrides(prefix).is_err());
+
+        std::env::remove_var(&key);
+    }
+}
@@ -0,0 +1,229 @@
+use std::time::{Duration, Instant};
+
+use serde_json::Value as SerdeValue;
+
+/// When the values of a feed are shared. By default every value received from the connector is shared.
+///
+/// ```ignore
+/// // only share the temperature when it changes, but at least every 10 minutes
+/// let model = model.with_feed_share_policy(
+///     "temperature",
+///     SharePolicy::on_change().with_keep_alive(Duration::from_secs(600)),
+/// );
+///
+/// // ignore the humidity jitter below 2% and share it at most every minute
+/// let model = model.with_feed_share_policy(
+///     "humidity",
+///     SharePolicy::always()
+///         .with_deadband(Deadband::Percent(2.0))
+///         .with_min_interval(Duration::from_secs(60)),
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SharePolicy {
+    on_change: bool,
+    keep_alive: Option<Duration>,
+    deadband: Option<Deadband>,
+    min_interval: Option<Duration>,
+}
+
+/// How much a numeric field has to move since the last share for the value to be shared.
+/// The fields which aren't numbers are shared when they change.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Deadband {
+    Absolute(f64),
+    /// Percentage of the last shared value
+    Percent(f64),
+}
+
+impl Deadband {
+    fn exceeded(&self, last: f64, current: f64) -> bool {
+        let delta = (current - last).abs();
+
+        match self {
+            Deadband::Absolute(threshold) => delta > *threshold,
+            Deadband::Percent(percent) => delta > last.abs() * percent / 100.0,
+        }
+    }
+
+    /// Compares the numbers field by field, the feed values being either a number
+    /// or an object of fields
+    fn changed(&self, last: &SerdeValue, current: &SerdeValue) -> bool {
+        match (last, current) {
+            (SerdeValue::Object(last), SerdeValue::Object(current)) => {
+                last.len() != current.len()
+                    || current
+                        .iter()
+                        .any(|(field, current)| match last.get(field) {
+                            Some(last) => self.changed(last, current),
+                            None => true,
+                        })
+            }
+            _ => match (last.as_f64(), current.as_f64()) {
+                (Some(last), Some(current)) => self.exceeded(last, current),
+                _ => last != current,
+            },
+        }
+    }
+}
+
+impl SharePolicy {
+    /// Shares every value
+    pub fn always() -> Self {
+        Self::default()
+    }
+
+    /// Only shares the values which differ from the last shared one
+    pub fn on_change() -> Self {
+        Self {
+            on_change: true,
+            ..Default::default()
+        }
+    }
+
+    /// Shares an unchanged value anyway when the last share is older than `keep_alive`.
+    /// It only applies to the values received from the connector, nothing is shared for a feed
+    /// missing from the data.
+    pub fn with_keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    /// Only shares the values whose numeric fields moved by more than the deadband
+    /// since the last share
+    pub fn with_deadband(mut self, deadband: Deadband) -> Self {
+        self.deadband = Some(deadband);
+        self
+    }
+
+    /// Doesn't share more often than every `min_interval`, even if the value changed
+    pub fn with_min_interval(mut self, min_interval: Duration) -> Self {
+        self.min_interval = Some(min_interval);
+        self
+    }
+
+    pub(crate) fn should_share(
+        &self,
+        last_share: Option<&LastShare>,
+        value: &SerdeValue,
+        now: Instant,
+    ) -> bool {
+        let last_share = match last_share {
+            Some(last_share) => last_share,
+            None => return true,
+        };
+
+        let elapsed = now.duration_since(last_share.shared_at);
+
+        if let Some(min_interval) = self.min_interval {
+            if elapsed < min_interval {
+                return false;
+            }
+        }
+
+        if let Some(keep_alive) = self.keep_alive {
+            if elapsed >= keep_alive {
+                return true;
+            }
+        }
+
+        match (self.deadband, self.on_change) {
+            (Some(deadband), _) => deadband.changed(&last_share.value, value),
+            (None, true) => last_share.value != *value,
+            (None, false) => true,
+        }
+    }
+}
+
+/// The last value shared to a twin feed
+#[derive(Debug, Clone)]
+pub(crate) struct LastShare {
+    pub value: SerdeValue,
+    pub shared_at: Instant,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn last_share(value: SerdeValue, shared_at: Instant) -> LastShare {
+        LastShare { value, shared_at }
+    }
+
+    #[test]
+    fn shares_the_first_value() {
+        let now = Instant::now();
+
+        assert!(SharePolicy::on_change().should_share(None, &json!(1), now));
+        assert!(SharePolicy::always()
+            .with_min_interval(Duration::from_secs(60))
+            .should_share(None, &json!(1), now));
+    }
+
+    #[test]
+    fn on_change_skips_the_unchanged_values() {
+        let now = Instant::now();
+        let last = last_share(json!({"value": 1}), now);
+        let policy = SharePolicy::on_change();
+
+        assert!(!policy.should_share(Some(&last), &json!({"value": 1}), now));
+        assert!(policy.should_share(Some(&last), &json!({"value": 2}), now));
+    }
+
+    #[test]
+    fn keep_alive_shares_the_unchanged_values() {
+        let now = Instant::now();
+        let last = last_share(json!(1), now);
+        let policy = SharePolicy::on_change().with_keep_alive(Duration::from_secs(600));
+
+        assert!(!policy.should_share(Some(&last), &json!(1), now + Duration::from_secs(599)));
+        assert!(policy.should_share(Some(&last), &json!(1), now + Duration::from_secs(600)));
+    }
+
+    #[test]
+    fn absolute_deadband() {
+        let now = Instant::now();
+        let last = last_share(json!({"temperature": 20.0, "unit": "C"}), now);
+        let policy = SharePolicy::always().with_deadband(Deadband::Absolute(0.5));
+
+        assert!(!policy.should_share(Some(&last), &json!({"temperature": 20.5, "unit": "C"}), now));
+        assert!(policy.should_share(Some(&last), &json!({"temperature": 19.4, "unit": "C"}), now));
+        assert!(policy.should_share(Some(&last), &json!({"temperature": 20.0, "unit": "F"}), now));
+        assert!(policy.should_share(Some(&last), &json!({"temperature": 20.0}), now));
+    }
+
+    #[test]
+    fn percent_deadband() {
+        let now = Instant::now();
+        let last = last_share(json!(50), now);
+        let policy = SharePolicy::always().with_deadband(Deadband::Percent(2.0));
+
+        assert!(!policy.should_share(Some(&last), &json!(51), now));
+        assert!(!policy.should_share(Some(&last), &json!(49), now));
+        assert!(policy.should_share(Some(&last), &json!(51.5), now));
+    }
+
+    #[test]
+    fn percent_deadband_from_zero() {
+        let now = Instant::now();
+        let last = last_share(json!(0), now);
+        let policy = SharePolicy::always().with_deadband(Deadband::Percent(2.0));
+
+        assert!(!policy.should_share(Some(&last), &json!(0), now));
+        assert!(policy.should_share(Some(&last), &json!(0.1), now));
+    }
+
+    #[test]
+    fn min_interval() {
+        let now = Instant::now();
+        let last = last_share(json!(1), now);
+        let policy = SharePolicy::always()
+            .with_min_interval(Duration::from_secs(60))
+            .with_keep_alive(Duration::from_secs(30));
+
+        assert!(!policy.should_share(Some(&last), &json!(2), now + Duration::from_secs(59)));
+        assert!(policy.should_share(Some(&last), &json!(2), now + Duration::from_secs(60)));
+    }
+}
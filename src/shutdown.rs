@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use actix::{Addr, System};
+use log::{error, info};
+
+use crate::constants::SHUTDOWN_DEADLINE;
+use crate::messages::Shutdown;
+use crate::model_actor::ModelActor;
+
+/// Stops the model actors cleanly: fetching stops, the twins finish their in-flight shares
+/// (and are deleted if `delete_twins` is set), a final heartbeat is shared
+/// and the `System` exits with code 0
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    models: Vec<Addr<ModelActor>>,
+    deadline: Duration,
+}
+
+impl ShutdownHandle {
+    pub fn new(models: Vec<Addr<ModelActor>>) -> Self {
+        Self {
+            models,
+            deadline: SHUTDOWN_DEADLINE,
+        }
+    }
+
+    /// How long the twins are given to finish their in-flight shares
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    pub async fn shutdown(&self) {
+        // the messages are queued straight away so the models shut down concurrently
+        let requests: Vec<_> = self
+            .models
+            .iter()
+            .map(|addr| {
+                addr.send(Shutdown {
+                    deadline: self.deadline,
+                })
+            })
+            .collect();
+
+        for request in requests {
+            if let Err(e) = request.await {
+                error!("failed to shut down a model {:?}", e);
+            }
+        }
+
+        System::current().stop_with_code(0);
+    }
+
+    /// Waits for SIGTERM or SIGINT, then shuts down
+    pub async fn shutdown_on_signal(self) {
+        match wait_for_signal().await {
+            Ok(signal) => info!("Received {}, shutting down", signal),
+            Err(e) => error!("failed to listen for the shutdown signals {:?}", e),
+        }
+
+        self.shutdown().await;
+    }
+}
+
+#[cfg(unix)]
+pub(crate) async fn wait_for_signal() -> Result<&'static str, std::io::Error> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sigint = signal(SignalKind::interrupt())?;
+
+    tokio::select! {
+        _ = sigterm.recv() => Ok("SIGTERM"),
+        _ = sigint.recv() => Ok("SIGINT"),
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) async fn wait_for_signal() -> Result<&'static str, std::io::Error> {
+    tokio::signal::ctrl_c().await?;
+
+    Ok("Ctrl-C")
+}
@@ -0,0 +1,92 @@
+//! Spans around a fetch cycle: the `get_data` call, the routing of each twin data by the model
+//! actor, the twin upserts, the feed shares and the property updates.
+//! They are no-ops unless the `tracing` feature is enabled, the `otlp` feature exports them to
+//! an OpenTelemetry collector.
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{Instrument, Span};
+
+#[cfg(feature = "tracing")]
+macro_rules! span {
+    ($($arg:tt)*) => {
+        tracing::info_span!($($arg)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! span {
+    ($($arg:tt)*) => {
+        $crate::trace::Span::none()
+    };
+}
+
+pub(crate) use span;
+
+/// Stands in for `tracing::Span` when the `tracing` feature is disabled
+#[cfg(not(feature = "tracing"))]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Span;
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    pub fn none() -> Self {
+        Self
+    }
+
+    pub fn current() -> Self {
+        Self
+    }
+
+    pub fn record<V>(&self, _field: &str, _value: V) -> &Self {
+        self
+    }
+
+    pub fn enter(&self) -> &Self {
+        self
+    }
+}
+
+/// Stands in for `tracing::Instrument` when the `tracing` feature is disabled
+#[cfg(not(feature = "tracing"))]
+pub(crate) trait Instrument: Sized {
+    fn instrument(self, _span: Span) -> Self {
+        self
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl<T> Instrument for T {}
+
+/// Exports the spans to the OpenTelemetry collector set by the standard `OTEL_EXPORTER_OTLP_*`
+/// environment variables (`http://localhost:4317` by default), over gRPC.
+/// Must be called from within the Tokio runtime, before starting the engine.
+#[cfg(feature = "otlp")]
+pub fn init_otlp(service_name: &str) -> Result<(), anyhow::Error> {
+    use opentelemetry::KeyValue;
+    use opentelemetry_sdk::{trace, Resource};
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic())
+        .with_trace_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                service_name.to_string(),
+            )])),
+        )
+        .install_batch(opentelemetry_sdk::runtime::Tokio)?;
+
+    tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .try_init()?;
+
+    Ok(())
+}
+
+/// Flushes the spans which weren't exported yet, called by `EngineHandle::run_until_signal`
+#[cfg(feature = "otlp")]
+pub fn shutdown_otlp() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
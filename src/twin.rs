@@ -3,6 +3,8 @@ use iotics_grpc_client::GeoLocation;
 #[derive(Debug, Clone)]
 pub struct Twin {
     pub model_did: String,
+    /// `ConnectorData::id` of the twin
+    pub id: String,
     pub seed: String,
     pub label: String,
     pub location: Option<GeoLocation>,
@@ -17,9 +19,16 @@ impl Twin {
     ) -> Self {
         Self {
             model_did,
+            id: String::new(),
             seed,
             label,
             location,
         }
     }
+
+    /// Sets the `ConnectorData::id` the inputs received by the twin are reported with
+    pub fn with_id(mut self, id: String) -> Self {
+        self.id = id;
+        self
+    }
 }
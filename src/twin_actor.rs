@@ -1,17 +1,37 @@
-use actix::{Actor, ActorContext, Addr, AsyncContext, Context, Handler, WrapFuture};
+use actix::{
+    Actor, ActorContext, ActorFutureExt, Addr, AsyncContext, Context, Handler, SpawnHandle,
+    WrapFuture,
+};
 use iotics_grpc_client::twin::crud::{delete_twin_with_channel, update_twin_with_channel};
+use iotics_grpc_client::twin::input::receive_input_messages_with_channel;
 use iotics_grpc_client::twin::share::share_data_with_channel;
 use iotics_grpc_client::twin::upsert::upsert_twin_with_channel;
 use log::{debug, error, warn};
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Instant, SystemTime};
 
 use iotics_grpc_client::{Channel, PropertyUpdate};
 use iotics_identity::create_twin_did_with_control_delegation;
 
 use crate::config::AuthBuilder;
-use crate::messages::{Cleanup, TwinCreationFailure, TwinCreationSuccess, TwinData, TwinDeleted};
+use crate::connector::{parse_message_data, Connector, ReceivedInput, ShareFailure};
+use crate::messages::{
+    ChannelHealthReport, ChannelsUpdated, Cleanup, FeedShareFailed, PropertiesUpdated,
+    TwinCreationFailure, TwinCreationSuccess, TwinData, TwinDeleted, TwinNotFound, TwinQuarantined,
+    TwinShutdown,
+};
+use crate::metrics::ModelMetrics;
 use crate::model_actor::ModelActor;
+use crate::retry::{
+    is_not_found_error, is_permanent_error, is_retryable_error, resubscribe_with_backoff,
+    retry_with_backoff_if,
+};
+use crate::settings::ModelSettings;
+use crate::share_policy::LastShare;
+use crate::trace::{span, Instrument, Span};
+use crate::twin_registry::{metadata_hash, TwinRegistry};
+use crate::validation::validate_feed_value;
 use crate::{
     constants::AGENT_TWIN_NAME,
     messages::{ShareConcurrencyReduction, TwinConcurrencyReduction},
@@ -25,36 +45,71 @@ pub struct TwinActor {
     auth_builder: Arc<AuthBuilder>,
     twin: Twin,
     model: Model,
+    connector: Arc<dyn Connector>,
+    settings: ModelSettings,
     twin_channel: Channel,
     feed_channel: Channel,
     twin_did: Option<String>,
+    /// The twin was adopted from the registry without being upserted
+    adopted: bool,
+    pending_data: Option<TwinData>,
     last_data_received_at: SystemTime,
     creation_in_flight: bool,
     shares_in_flight: usize,
+    updates_in_flight: usize,
+    shutdown: Option<TwinShutdown>,
+    last_shares: HashMap<String, LastShare>,
+    input_subscriptions: Vec<SpawnHandle>,
+    registry: Option<Arc<TwinRegistry>>,
+    metrics: Arc<ModelMetrics>,
+    creation_span: Span,
+}
+
+/// What the twin actors of a model have in common, built by the model actor
+#[derive(Debug, Clone)]
+pub struct TwinContext {
+    pub auth_builder: Arc<AuthBuilder>,
+    pub model: Model,
+    pub connector: Arc<dyn Connector>,
+    pub settings: ModelSettings,
+    pub twin_channel: Channel,
+    pub feed_channel: Channel,
+    pub registry: Option<Arc<TwinRegistry>>,
+    pub metrics: Arc<ModelMetrics>,
 }
 
 impl TwinActor {
-    pub fn new(
-        model_addr: Addr<ModelActor>,
-        auth_builder: Arc<AuthBuilder>,
-        twin: Twin,
-        model: Model,
-        twin_channel: Channel,
-        feed_channel: Channel,
-    ) -> Self {
+    pub fn new(model_addr: Addr<ModelActor>, twin: Twin, context: TwinContext) -> Self {
         Self {
             model_addr,
-            auth_builder,
+            auth_builder: context.auth_builder,
             twin,
-            model,
-            twin_channel,
-            feed_channel,
+            model: context.model,
+            connector: context.connector,
+            settings: context.settings,
+            twin_channel: context.twin_channel,
+            feed_channel: context.feed_channel,
             twin_did: None,
+            adopted: false,
+            pending_data: None,
             last_data_received_at: SystemTime::now(),
             creation_in_flight: false,
             shares_in_flight: 0,
+            updates_in_flight: 0,
+            shutdown: None,
+            last_shares: HashMap::new(),
+            input_subscriptions: Vec::new(),
+            registry: context.registry,
+            metrics: context.metrics,
+            creation_span: Span::none(),
         }
     }
+
+    /// Makes the upsert span a child of the given one
+    pub(crate) fn with_creation_span(mut self, creation_span: Span) -> Self {
+        self.creation_span = creation_span;
+        self
+    }
 }
 
 impl Actor for TwinActor {
@@ -62,55 +117,94 @@ impl Actor for TwinActor {
 
     fn started(&mut self, ctx: &mut Self::Context) {
         debug!("Twin {} actor started", &self.twin.label);
+        ModelMetrics::add(&self.metrics.twins_running, 1);
 
         self.creation_in_flight = true;
 
         let addr = ctx.address();
 
+        let properties = self
+            .model
+            .build_twin_properties(&self.twin.model_did, &self.twin.label);
+        let feeds = self.model.get_feeds(false);
+        let inputs = self.model.get_inputs();
+        let metadata_hash = metadata_hash(&(&properties, &feeds, &inputs, &self.twin.location));
+
+        // the twin didn't change since it was last upserted
+        if let Some(registry) = self.registry.as_ref() {
+            if let Some(twin_did) = registry.get(&self.twin.seed, &metadata_hash) {
+                debug!("Twin {} adopted from the registry", &self.twin.label);
+                self.adopted = true;
+                ctx.notify(TwinCreationSuccess { twin_did });
+                return;
+            }
+        }
+
         let auth_builder = self.auth_builder.clone();
         let twin = self.twin.clone();
-        let model = self.model.clone();
         let twin_channel = self.twin_channel.clone();
+        let model_addr = self.model_addr.clone();
+        let creation_backoff = self.settings.twin_creation_backoff.clone();
+        let registry = self.registry.clone();
+        let metrics = self.metrics.clone();
+        let upsert_span =
+            span!(parent: &self.creation_span, "upsert_twin", twin = %self.twin.label);
 
         let fut = async move {
-            let properties = model.build_twin_properties(&twin.model_did, &twin.label);
-
-            let result = async {
-                let identity_config = auth_builder
-                    .get_identity_config()
-                    .expect("failed to get the identity config");
-                let twin_did = create_twin_did_with_control_delegation(
-                    &identity_config,
-                    &twin.seed,
-                    AGENT_TWIN_NAME,
-                )?;
-
-                upsert_twin_with_channel(
-                    auth_builder,
-                    twin_channel,
-                    &twin_did,
-                    properties,
-                    model.get_feeds(false),
-                    Vec::new(),
-                    twin.location,
-                )
-                .await?;
-
-                Ok::<String, anyhow::Error>(twin_did)
-            }
+            let result = retry_with_backoff_if(
+                &creation_backoff,
+                &format!("Twin {} creation", &twin.label),
+                |error| !is_permanent_error(error),
+                || async {
+                    let identity_config = auth_builder.get_identity_config()?;
+                    let twin_did = create_twin_did_with_control_delegation(
+                        &identity_config,
+                        &twin.seed,
+                        AGENT_TWIN_NAME,
+                    )?;
+
+                    auth_builder
+                        .retry_on_auth_error(|| {
+                            upsert_twin_with_channel(
+                                auth_builder.clone(),
+                                twin_channel.clone(),
+                                &twin_did,
+                                properties.clone(),
+                                feeds.clone(),
+                                inputs.clone(),
+                                twin.location.clone(),
+                            )
+                        })
+                        .await?;
+
+                    Ok::<String, anyhow::Error>(twin_did)
+                },
+            )
+            .instrument(upsert_span)
             .await;
 
+            let mut health_report = ChannelHealthReport::default();
+            health_report.record(&result);
+            model_addr.do_send(health_report);
+
             match result {
                 Ok(twin_did) => {
-                    addr.try_send(TwinCreationSuccess { twin_did })
-                        .expect("failed to send TwinCreationSuccess message to self");
+                    ModelMetrics::increment(&metrics.twins_created);
+
+                    if let Some(registry) = registry.as_ref() {
+                        registry.record(&twin.seed, &twin_did, &metadata_hash);
+                    }
+
+                    addr.do_send(TwinCreationSuccess { twin_did });
                 }
                 Err(error) => {
-                    addr.try_send(TwinCreationFailure {
+                    ModelMetrics::increment(&metrics.twins_failed);
+
+                    addr.do_send(TwinCreationFailure {
                         twin_label: twin.label.clone(),
+                        permanent: is_permanent_error(&error),
                         error,
-                    })
-                    .expect("failed to send TwinCreationFailure message to self");
+                    });
                 }
             }
         }
@@ -126,22 +220,18 @@ impl Actor for TwinActor {
             warn!("Twin {} stopped while creating", self.twin.label);
 
             // Send the TwinConcurrencyReduction message to the model actor
-            self.model_addr
-                .try_send(TwinConcurrencyReduction {
-                    twin_seed: Some(self.twin.seed.clone()),
-                })
-                .expect("failed to send TwinConcurrencyReduction message");
+            self.model_addr.do_send(TwinConcurrencyReduction {
+                twin_seed: Some(self.twin.seed.clone()),
+            });
         }
 
         if self.shares_in_flight > 0 {
             warn!("Twin {} stopped while sharing", self.twin.label);
 
             // Send the ShareConcurrencyReduction message to the model actor
-            self.model_addr
-                .try_send(ShareConcurrencyReduction {
-                    shares_count: self.shares_in_flight,
-                })
-                .expect("failed to send ShareConcurrencyReduction message");
+            self.model_addr.do_send(ShareConcurrencyReduction {
+                shares_count: self.shares_in_flight,
+            });
         }
 
         actix::Running::Stop
@@ -149,24 +239,31 @@ impl Actor for TwinActor {
 
     fn stopped(&mut self, _: &mut Self::Context) {
         debug!("Twin {} actor stopped", self.twin.label);
+        ModelMetrics::sub(&self.metrics.twins_running, 1);
     }
 }
 
 impl Handler<TwinCreationSuccess> for TwinActor {
     type Result = ();
 
-    fn handle(&mut self, message: TwinCreationSuccess, _: &mut Context<Self>) -> Self::Result {
+    fn handle(&mut self, message: TwinCreationSuccess, ctx: &mut Context<Self>) -> Self::Result {
         debug!("Twin {} actor got message {:?}", self.twin.label, message);
         self.twin_did.replace(message.twin_did);
+        self.subscribe_inputs(ctx);
 
         // Send the TwinConcurrencyReduction message to the model actor
-        self.model_addr
-            .try_send(TwinConcurrencyReduction {
-                twin_seed: Some(self.twin.seed.clone()),
-            })
-            .expect("failed to send TwinConcurrencyReduction message");
+        self.model_addr.do_send(TwinConcurrencyReduction {
+            twin_seed: Some(self.twin.seed.clone()),
+        });
 
         self.creation_in_flight = false;
+
+        // Hand the data received while creating back to the model actor
+        // so it goes through the share throttling
+        if let Some(pending_data) = self.pending_data.take() {
+            ModelMetrics::add(&self.metrics.queued_twin_data, 1);
+            self.model_addr.do_send(pending_data);
+        }
     }
 }
 
@@ -176,15 +273,31 @@ impl Handler<TwinCreationFailure> for TwinActor {
     fn handle(&mut self, message: TwinCreationFailure, ctx: &mut Context<Self>) -> Self::Result {
         debug!("Twin {} actor got message {:?}", self.twin.label, message);
 
+        if message.permanent {
+            // Don't re-attempt the creation on every fetch
+            self.model_addr.do_send(TwinQuarantined {
+                twin_seed: self.twin.seed.clone(),
+                twin_label: message.twin_label,
+                error: message.error,
+            });
+        }
+
         // Send the TwinConcurrencyReduction message to the model actor
-        self.model_addr
-            .try_send(TwinConcurrencyReduction {
-                twin_seed: Some(self.twin.seed.clone()),
-            })
-            .expect("failed to send TwinConcurrencyReduction message");
+        self.model_addr.do_send(TwinConcurrencyReduction {
+            twin_seed: Some(self.twin.seed.clone()),
+        });
 
         self.creation_in_flight = false;
 
+        // Hand the data received while creating back to the model actor, a new twin actor
+        // attempts the creation again with it unless the twin is quarantined
+        if let Some(pending_data) = self.pending_data.take() {
+            if !message.permanent {
+                ModelMetrics::add(&self.metrics.queued_twin_data, 1);
+                self.model_addr.do_send(pending_data);
+            }
+        }
+
         // Stop the actor
         ctx.stop();
     }
@@ -193,47 +306,178 @@ impl Handler<TwinCreationFailure> for TwinActor {
 impl Handler<TwinData> for TwinActor {
     type Result = ();
 
-    fn handle(&mut self, message: TwinData, ctx: &mut Context<Self>) -> Self::Result {
-        // twin_did should never be None
-        // because we're not sharing data until the twin is created
-        let twin_did = self.twin_did.as_ref().expect("this should not happen");
+    fn handle(&mut self, mut message: TwinData, ctx: &mut Context<Self>) -> Self::Result {
+        if self.shutdown.is_some() {
+            return;
+        }
 
+        let twin_did = match self.twin_did.as_ref() {
+            Some(twin_did) => twin_did,
+            None => {
+                // The twin is still being created, only keep the latest data
+                self.last_data_received_at = SystemTime::now();
+                self.pending_data.replace(message);
+                return;
+            }
+        };
+
+        // the model actor counted every feed as a share
         let shares_count = message.data.feeds.len();
         self.shares_in_flight += shares_count;
 
         let addr = ctx.address();
         self.last_data_received_at = SystemTime::now();
 
+        let mut invalid_values = Vec::new();
+
+        if self.settings.validate_feed_values {
+            for (feed_id, value) in std::mem::take(&mut message.data.feeds) {
+                match validate_feed_value(&self.model, &feed_id, value.clone()) {
+                    Ok(value) => {
+                        message.data.feeds.insert(feed_id, value);
+                    }
+                    Err(error) => {
+                        error!(
+                            "Twin {} dropping invalid feed value: {}",
+                            &self.twin.label, error
+                        );
+
+                        invalid_values.push(ShareFailure {
+                            twin_id: message.data.id.clone(),
+                            twin_did: twin_did.clone(),
+                            feed_id,
+                            value,
+                            error: error.into(),
+                        });
+                    }
+                }
+            }
+        }
+
+        // Skip the values which the share policies don't require sharing.
+        // The values are recorded as shared straight away so a value arriving
+        // while the previous one is still being shared is compared to it
+        let now = Instant::now();
+        let model = &self.model;
+        let last_shares = &mut self.last_shares;
+
+        message.data.feeds.retain(|feed_id, value| {
+            let share =
+                model
+                    .get_share_policy(feed_id)
+                    .should_share(last_shares.get(feed_id), value, now);
+
+            if share {
+                last_shares.insert(
+                    feed_id.clone(),
+                    LastShare {
+                        value: value.clone(),
+                        shared_at: now,
+                    },
+                );
+            }
+
+            share
+        });
+
+        let skipped = shares_count - message.data.feeds.len() - invalid_values.len();
+        if skipped > 0 {
+            debug!(
+                "Twin {} skipped {} feed values because of the share policies",
+                &self.twin.label, skipped
+            );
+        }
+
+        if message.data.feeds.is_empty()
+            && message.data.properties.is_empty()
+            && invalid_values.is_empty()
+        {
+            ctx.notify(ShareConcurrencyReduction { shares_count });
+            return;
+        }
+
+        // the shutdown waits for the property updates too
+        let update_properties = !message.data.properties.is_empty();
+        if update_properties {
+            self.updates_in_flight += 1;
+        }
+
         let auth_builder = self.auth_builder.clone();
         let label = self.twin.label.clone();
         let twin_did = twin_did.clone();
 
         let twin_channel = self.twin_channel.clone();
         let feed_channel = self.feed_channel.clone();
+        let model_addr = self.model_addr.clone();
+        let connector = self.connector.clone();
+        let share_backoff = self.settings.share_backoff.clone();
+        let metrics = self.metrics.clone();
+        let adopted = self.adopted;
 
         let fut = async move {
+            for failure in invalid_values {
+                connector.on_invalid_value(failure).await;
+            }
+
+            let mut health_report = ChannelHealthReport::default();
+            let mut shared = false;
+
             for (feed_id, feed_data) in &message.data.feeds {
                 let data = feed_data.to_string().as_bytes().to_vec();
 
-                let result = share_data_with_channel(
-                    auth_builder.clone(),
-                    feed_channel.clone(),
-                    &twin_did,
-                    feed_id,
-                    data,
-                    true,
+                let result = retry_with_backoff_if(
+                    &share_backoff,
+                    &format!("Twin {label} share of {feed_id} feed data"),
+                    is_retryable_error,
+                    || {
+                        auth_builder.retry_on_auth_error(|| {
+                            share_data_with_channel(
+                                auth_builder.clone(),
+                                feed_channel.clone(),
+                                &twin_did,
+                                feed_id,
+                                data.clone(),
+                                true,
+                            )
+                        })
+                    },
                 )
+                .instrument(span!(parent: &message.span, "share", twin = %label, feed = %feed_id))
                 .await;
 
-                if let Err(e) = result {
-                    error!("failed to share data to twin {} {:?}", &twin_did, e);
+                health_report.record(&result);
+
+                if let Err(error) = result {
+                    error!("failed to share data to twin {} {:?}", &twin_did, error);
+                    ModelMetrics::increment(&metrics.share_failures);
+
+                    if adopted && is_not_found_error(&error) {
+                        addr.do_send(TwinNotFound);
+                    }
+
+                    // so the value isn't skipped next time
+                    addr.do_send(FeedShareFailed {
+                        feed_id: feed_id.clone(),
+                    });
+
+                    connector
+                        .on_share_failure(ShareFailure {
+                            twin_id: message.data.id.clone(),
+                            twin_did: twin_did.clone(),
+                            feed_id: feed_id.clone(),
+                            value: feed_data.clone(),
+                            error,
+                        })
+                        .await;
                 } else {
                     debug!("Twin {} shared {} feed data", &label, &feed_id);
+                    ModelMetrics::increment(&metrics.shares);
+                    shared = true;
                 }
             }
 
-            if !message.data.properties.is_empty() {
-                let deleted_by_key = message
+            if update_properties {
+                let deleted_by_key: Vec<String> = message
                     .data
                     .properties
                     .clone()
@@ -241,18 +485,24 @@ impl Handler<TwinData> for TwinActor {
                     .map(|p| p.key)
                     .collect();
 
-                let result = update_twin_with_channel(
-                    auth_builder,
-                    twin_channel,
-                    &twin_did,
-                    PropertyUpdate {
-                        cleared_all: false,
-                        added: message.data.properties.clone(),
-                        deleted_by_key,
-                        ..Default::default()
-                    },
-                )
-                .await;
+                let result = auth_builder
+                    .retry_on_auth_error(|| {
+                        update_twin_with_channel(
+                            auth_builder.clone(),
+                            twin_channel.clone(),
+                            &twin_did,
+                            PropertyUpdate {
+                                cleared_all: false,
+                                added: message.data.properties.clone(),
+                                deleted_by_key: deleted_by_key.clone(),
+                                ..Default::default()
+                            },
+                        )
+                    })
+                    .instrument(span!(parent: &message.span, "update_twin", twin = %label))
+                    .await;
+
+                health_report.record(&result);
 
                 if let Err(e) = result {
                     error!(
@@ -262,11 +512,20 @@ impl Handler<TwinData> for TwinActor {
                 } else {
                     debug!("Twin {} properties updated", &twin_did);
                 }
+
+                addr.do_send(PropertiesUpdated);
+            }
+
+            if !health_report.is_empty() {
+                model_addr.do_send(health_report);
+            }
+
+            if shared {
+                metrics.record_activity();
             }
 
             // Send the ShareConcurrencyReduction to self
-            addr.try_send(ShareConcurrencyReduction { shares_count })
-                .expect("failed to send ShareConcurrencyReduction message to self");
+            addr.do_send(ShareConcurrencyReduction { shares_count });
         }
         .into_actor(self);
 
@@ -280,14 +539,50 @@ impl Handler<ShareConcurrencyReduction> for TwinActor {
     fn handle(
         &mut self,
         message: ShareConcurrencyReduction,
-        _: &mut Context<Self>,
+        ctx: &mut Context<Self>,
     ) -> Self::Result {
         // Send the ShareConcurrencyReduction message to the model actor
-        self.model_addr
-            .try_send(message.clone())
-            .expect("failed to send ShareConcurrencyReduction message");
+        self.model_addr.do_send(message.clone());
 
         self.shares_in_flight -= message.shares_count;
+
+        if self.shutdown.is_some() && !self.is_busy() {
+            self.finish_shutdown(ctx);
+        }
+    }
+}
+
+impl Handler<PropertiesUpdated> for TwinActor {
+    type Result = ();
+
+    fn handle(&mut self, _: PropertiesUpdated, ctx: &mut Context<Self>) -> Self::Result {
+        self.updates_in_flight -= 1;
+
+        if self.shutdown.is_some() && !self.is_busy() {
+            self.finish_shutdown(ctx);
+        }
+    }
+}
+
+impl Handler<FeedShareFailed> for TwinActor {
+    type Result = ();
+
+    fn handle(&mut self, message: FeedShareFailed, _: &mut Context<Self>) -> Self::Result {
+        self.last_shares.remove(&message.feed_id);
+    }
+}
+
+impl Handler<ChannelsUpdated> for TwinActor {
+    type Result = ();
+
+    fn handle(&mut self, message: ChannelsUpdated, ctx: &mut Context<Self>) -> Self::Result {
+        debug!("Twin {} actor got new channels", self.twin.label);
+
+        self.twin_channel = message.twin_channel;
+        self.feed_channel = message.feed_channel;
+
+        // the input subscriptions are on the broken channel
+        self.subscribe_inputs(ctx);
     }
 }
 
@@ -295,6 +590,28 @@ impl Handler<TwinDeleted> for TwinActor {
     type Result = ();
 
     fn handle(&mut self, _: TwinDeleted, ctx: &mut Context<Self>) -> Self::Result {
+        if let Some(registry) = self.registry.as_ref() {
+            registry.remove(&self.twin.seed);
+        }
+
+        ctx.stop();
+    }
+}
+
+impl Handler<TwinNotFound> for TwinActor {
+    type Result = ();
+
+    fn handle(&mut self, _: TwinNotFound, ctx: &mut Context<Self>) -> Self::Result {
+        warn!(
+            "Twin {} adopted from the registry is not on the host anymore, it is upserted with the next data",
+            &self.twin.label
+        );
+
+        if let Some(registry) = self.registry.as_ref() {
+            registry.remove(&self.twin.seed);
+        }
+
+        // the model actor starts a new twin actor, which upserts the twin, for the next data
         ctx.stop();
     }
 }
@@ -303,36 +620,166 @@ impl Handler<Cleanup> for TwinActor {
     type Result = ();
 
     fn handle(&mut self, message: Cleanup, ctx: &mut Context<Self>) -> Self::Result {
-        let expire_at = self
+        // never expires if the cleanup interval overflows
+        let expired = self
             .last_data_received_at
             .checked_add(message.cleanup_every_secs)
-            .expect("this should not happen");
+            .is_some_and(|expire_at| SystemTime::now() > expire_at);
 
-        if SystemTime::now() > expire_at {
+        if expired {
             if message.delete_twins {
-                let twin_did = self.twin_did.clone().expect("This should not happen");
-                let auth_builder = self.auth_builder.clone();
-                let twin_channel = self.twin_channel.clone();
-                let addr = ctx.address();
-
-                let fut = async move {
-                    let result =
-                        delete_twin_with_channel(auth_builder.clone(), twin_channel, &twin_did)
-                            .await;
-                    if let Err(e) = result {
-                        error!("Failed to delete twin {} {:?}.", &twin_did, e);
-                    } else {
-                        debug!("Twin {} deleted", &twin_did);
-                        addr.try_send(TwinDeleted)
-                            .expect("Failed to send TwinDeleted message");
-                    }
-                }
-                .into_actor(self);
+                self.delete_twin(ctx, false);
+            } else {
+                ctx.stop();
+            }
+        }
+    }
+}
+
+impl Handler<TwinShutdown> for TwinActor {
+    type Result = ();
+
+    fn handle(&mut self, message: TwinShutdown, ctx: &mut Context<Self>) -> Self::Result {
+        debug!("Twin {} actor shutting down", self.twin.label);
+
+        self.shutdown = Some(message);
+        self.pending_data = None;
+
+        if !self.is_busy() {
+            self.finish_shutdown(ctx);
+        }
+    }
+}
+
+impl TwinActor {
+    /// Receives the messages sent to the inputs of the twin and passes them to the connector.
+    /// The subscriptions are re-opened with the channel backoff when they fail or end.
+    fn subscribe_inputs(&mut self, ctx: &mut Context<Self>) {
+        for handle in self.input_subscriptions.drain(..) {
+            ctx.cancel_future(handle);
+        }
 
-                ctx.spawn(fut);
+        let twin_did = match self.twin_did.clone() {
+            Some(twin_did) => twin_did,
+            None => return,
+        };
+
+        for input in self.model.get_inputs() {
+            let auth_builder = self.auth_builder.clone();
+            let twin_channel = self.twin_channel.clone();
+            let model_addr = self.model_addr.clone();
+            let connector = self.connector.clone();
+            let label = self.twin.label.clone();
+            let twin_id = self.twin.id.clone();
+            let twin_did = twin_did.clone();
+            let backoff = self.settings.channel_backoff.clone();
+
+            let fut = async move {
+                let description = format!("Twin {} {} input subscription", &label, &input.id);
+                let connector = &connector;
+                let twin_id = &twin_id;
+                let twin_did = &twin_did;
+                let input_id = &input.id;
+
+                resubscribe_with_backoff(
+                    &backoff,
+                    &description,
+                    || {
+                        auth_builder.retry_on_auth_error(|| {
+                            receive_input_messages_with_channel(
+                                auth_builder.clone(),
+                                twin_channel.clone(),
+                                twin_did,
+                                input_id,
+                            )
+                        })
+                    },
+                    |result| {
+                        let mut health_report = ChannelHealthReport::default();
+                        health_report.record(result);
+                        model_addr.do_send(health_report);
+                    },
+                    move |response| {
+                        let data = response
+                            .payload
+                            .and_then(|payload| payload.message)
+                            .map(|message| message.data);
+
+                        async move {
+                            if let Some(data) = data {
+                                connector
+                                    .on_input(ReceivedInput {
+                                        twin_id: twin_id.clone(),
+                                        twin_did: twin_did.clone(),
+                                        input_id: input_id.clone(),
+                                        value: parse_message_data(&data),
+                                    })
+                                    .await;
+                            }
+                        }
+                    },
+                )
+                .await;
+            }
+            .into_actor(self);
+
+            self.input_subscriptions.push(ctx.spawn(fut));
+        }
+    }
+
+    /// Deletes the twin from the host and stops the actor.
+    /// If the deletion fails the actor only stops when `stop_on_failure` is set.
+    fn delete_twin(&mut self, ctx: &mut Context<Self>, stop_on_failure: bool) {
+        let twin_did = match self.twin_did.clone() {
+            Some(twin_did) => twin_did,
+            None => {
+                // the twin was never created
+                ctx.stop();
+                return;
+            }
+        };
+
+        let auth_builder = self.auth_builder.clone();
+        let twin_channel = self.twin_channel.clone();
+        let metrics = self.metrics.clone();
+
+        let fut = async move {
+            let result = auth_builder
+                .retry_on_auth_error(|| {
+                    delete_twin_with_channel(auth_builder.clone(), twin_channel.clone(), &twin_did)
+                })
+                .await;
+            if let Err(e) = result {
+                error!("Failed to delete twin {} {:?}.", &twin_did, e);
+                false
             } else {
+                debug!("Twin {} deleted", &twin_did);
+                ModelMetrics::increment(&metrics.twins_deleted);
+                true
+            }
+        }
+        .into_actor(self)
+        .map(move |deleted, _, ctx| {
+            if deleted {
+                ctx.notify(TwinDeleted);
+            } else if stop_on_failure {
+                // the twin is still on the host, keep its registry entry
                 ctx.stop();
             }
+        });
+
+        ctx.spawn(fut);
+    }
+
+    /// Whether feed shares or property updates are in flight
+    fn is_busy(&self) -> bool {
+        self.shares_in_flight > 0 || self.updates_in_flight > 0
+    }
+
+    fn finish_shutdown(&mut self, ctx: &mut Context<Self>) {
+        match self.shutdown.as_ref() {
+            Some(shutdown) if shutdown.delete_twins => self.delete_twin(ctx, true),
+            _ => ctx.stop(),
         }
     }
 }
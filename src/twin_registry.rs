@@ -0,0 +1,170 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// The twins upserted by a model, persisted to a JSON file so the unchanged twins are adopted
+/// on startup instead of being upserted again. Enabled by `ModelSettings::registry_path`.
+///
+/// The file is a cache: when it is missing or can't be read every twin is upserted.
+#[derive(Debug)]
+pub struct TwinRegistry {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, RegistryEntry>>,
+    dirty: AtomicBool,
+}
+
+/// A twin upserted by the engine, keyed by its seed in the registry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryEntry {
+    pub twin_did: String,
+    /// Hash of the properties, feeds, inputs and location of the last upsert
+    pub metadata_hash: String,
+    /// Unix timestamp of the last upsert, in seconds
+    pub upserted_at: u64,
+}
+
+impl TwinRegistry {
+    /// Loads the registry from the file, starting empty if it doesn't exist yet
+    pub fn open(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_path_buf();
+
+        let entries = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!(
+                    "Ignoring the twin registry {}, it can't be parsed {:?}",
+                    path.display(),
+                    e
+                );
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                warn!(
+                    "Ignoring the twin registry {}, it can't be read {:?}",
+                    path.display(),
+                    e
+                );
+                HashMap::new()
+            }
+        };
+
+        Self {
+            path,
+            entries: Mutex::new(entries),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries
+            .lock()
+            .expect("twin registry lock poisoned")
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The DID of the twin if it was upserted with the same metadata
+    pub fn get(&self, twin_seed: &str, metadata_hash: &str) -> Option<String> {
+        self.entries
+            .lock()
+            .expect("twin registry lock poisoned")
+            .get(twin_seed)
+            .filter(|entry| entry.metadata_hash == metadata_hash)
+            .map(|entry| entry.twin_did.clone())
+    }
+
+    pub fn record(&self, twin_seed: &str, twin_did: &str, metadata_hash: &str) {
+        let upserted_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+
+        self.entries
+            .lock()
+            .expect("twin registry lock poisoned")
+            .insert(
+                twin_seed.to_string(),
+                RegistryEntry {
+                    twin_did: twin_did.to_string(),
+                    metadata_hash: metadata_hash.to_string(),
+                    upserted_at,
+                },
+            );
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    pub fn remove(&self, twin_seed: &str) {
+        let removed = self
+            .entries
+            .lock()
+            .expect("twin registry lock poisoned")
+            .remove(twin_seed);
+
+        if removed.is_some() {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Forgets the twins which aren't in `twin_dids`, e.g. deleted from the host by someone else.
+    /// Returns the number of entries removed.
+    pub fn retain_twin_dids(&self, twin_dids: &HashSet<String>) -> usize {
+        let mut entries = self.entries.lock().expect("twin registry lock poisoned");
+        let count = entries.len();
+
+        entries.retain(|_, entry| twin_dids.contains(&entry.twin_did));
+
+        let removed = count - entries.len();
+        if removed > 0 {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+
+        removed
+    }
+
+    /// Writes the registry to its file if it changed since the last save
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let content = {
+            let entries = self.entries.lock().expect("twin registry lock poisoned");
+            serde_json::to_string(&*entries)
+        };
+
+        // write to a temporary file first so a crash can't leave a truncated registry
+        let result = content.map_err(anyhow::Error::from).and_then(|content| {
+            let tmp_path = self.path.with_extension("tmp");
+            std::fs::write(&tmp_path, content)?;
+            std::fs::rename(&tmp_path, &self.path)?;
+            Ok(())
+        });
+
+        if result.is_err() {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+
+        result
+    }
+}
+
+/// Hashes the metadata of a twin upsert, it only has to be stable for a given build of the connector
+pub(crate) fn metadata_hash(metadata: &impl Debug) -> String {
+    // FNV-1a, the std hasher isn't guaranteed to be stable across releases
+    let hash = format!("{metadata:?}")
+        .bytes()
+        .fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+
+    format!("{hash:016x}")
+}
@@ -0,0 +1,353 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use iotics_grpc_client::FeedValue;
+use serde_json::{Number, Value as SerdeValue};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::constants::{MAX_TIMESTAMP_SECS, XSD_DATA_TYPES};
+use crate::model::Model;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    UnknownFeed {
+        feed_id: String,
+    },
+    /// The feed values are shared as an object keyed by the `FeedValue` labels
+    NotAnObject {
+        feed_id: String,
+    },
+    UnknownField {
+        feed_id: String,
+        field: String,
+    },
+    Mismatch {
+        feed_id: String,
+        field: String,
+        data_type: String,
+        value: SerdeValue,
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::UnknownFeed { feed_id } => {
+                write!(f, "feed {feed_id} is not declared on the model")
+            }
+            ValidationError::NotAnObject { feed_id } => {
+                write!(f, "the value of feed {feed_id} is not an object")
+            }
+            ValidationError::UnknownField { feed_id, field } => {
+                write!(f, "{field} is not a value of feed {feed_id}")
+            }
+            ValidationError::Mismatch {
+                feed_id,
+                field,
+                data_type,
+                value,
+            } => write!(f, "{feed_id}.{field} expects {data_type}, got {value}"),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Checks a value shared to a feed against the `FeedValue`s the model declared for it and
+/// coerces the obvious mismatches: numeric strings to numbers, unix timestamps to RFC3339, ...
+/// The values whose data type isn't known are left as they are.
+pub fn validate_feed_value(
+    model: &Model,
+    feed_id: &str,
+    value: SerdeValue,
+) -> Result<SerdeValue, ValidationError> {
+    let feed = model
+        .get_feed(feed_id)
+        .ok_or_else(|| ValidationError::UnknownFeed {
+            feed_id: feed_id.to_string(),
+        })?;
+
+    let fields = match value {
+        SerdeValue::Object(fields) => fields,
+        _ => {
+            return Err(ValidationError::NotAnObject {
+                feed_id: feed_id.to_string(),
+            })
+        }
+    };
+
+    fields
+        .into_iter()
+        .map(|(field, value)| {
+            let feed_value = feed
+                .values
+                .iter()
+                .find(|feed_value| feed_value.label == field)
+                .ok_or_else(|| ValidationError::UnknownField {
+                    feed_id: feed_id.to_string(),
+                    field: field.clone(),
+                })?;
+
+            match coerce(feed_value, &value) {
+                Some(value) => Ok((field, value)),
+                None => Err(ValidationError::Mismatch {
+                    feed_id: feed_id.to_string(),
+                    field,
+                    data_type: feed_value.data_type.clone(),
+                    value,
+                }),
+            }
+        })
+        .collect::<Result<_, _>>()
+        .map(SerdeValue::Object)
+}
+
+fn coerce(feed_value: &FeedValue, value: &SerdeValue) -> Option<SerdeValue> {
+    if value.is_null() {
+        return None;
+    }
+
+    match data_type_name(&feed_value.data_type) {
+        name @ ("integer" | "int" | "long" | "short" | "byte" | "nonNegativeInteger"
+        | "positiveInteger" | "nonPositiveInteger" | "negativeInteger" | "unsignedLong"
+        | "unsignedInt" | "unsignedShort" | "unsignedByte") => to_integer(value)
+            .filter(|integer| integer_bounds(name).contains(integer))
+            .and_then(from_integer),
+        "decimal" | "double" | "float" => match value {
+            SerdeValue::Number(_) => Some(value.clone()),
+            SerdeValue::String(text) => text
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .and_then(Number::from_f64)
+                .map(SerdeValue::Number),
+            _ => None,
+        },
+        "boolean" => match value {
+            SerdeValue::Bool(_) => Some(value.clone()),
+            SerdeValue::String(text) => match text.trim() {
+                "true" | "1" => Some(SerdeValue::Bool(true)),
+                "false" | "0" => Some(SerdeValue::Bool(false)),
+                _ => None,
+            },
+            _ => None,
+        },
+        "string" | "anyURI" => match value {
+            SerdeValue::String(_) => Some(value.clone()),
+            SerdeValue::Number(number) => Some(SerdeValue::String(number.to_string())),
+            SerdeValue::Bool(bool) => Some(SerdeValue::String(bool.to_string())),
+            _ => None,
+        },
+        "dateTime" => match value {
+            SerdeValue::String(text) if OffsetDateTime::parse(text, &Rfc3339).is_ok() => {
+                Some(value.clone())
+            }
+            _ => to_integer(value)
+                .and_then(to_date_time)
+                .map(SerdeValue::String),
+        },
+        _ => Some(value.clone()),
+    }
+}
+
+// the data types are the XML schema ones, with or without the prefix
+fn data_type_name(data_type: &str) -> &str {
+    data_type.rsplit([':', '#']).next().unwrap_or_default()
+}
+
+pub(crate) fn is_known_data_type(data_type: &str) -> bool {
+    XSD_DATA_TYPES.contains(&data_type_name(data_type))
+}
+
+// the range of the XML schema integer types, `integer` being unbounded
+fn integer_bounds(name: &str) -> RangeInclusive<i128> {
+    match name {
+        "long" => i64::MIN.into()..=i64::MAX.into(),
+        "int" => i32::MIN.into()..=i32::MAX.into(),
+        "short" => i16::MIN.into()..=i16::MAX.into(),
+        "byte" => i8::MIN.into()..=i8::MAX.into(),
+        "nonNegativeInteger" => 0..=i128::MAX,
+        "positiveInteger" => 1..=i128::MAX,
+        "nonPositiveInteger" => i128::MIN..=0,
+        "negativeInteger" => i128::MIN..=-1,
+        "unsignedLong" => 0..=u64::MAX.into(),
+        "unsignedInt" => 0..=u32::MAX.into(),
+        "unsignedShort" => 0..=u16::MAX.into(),
+        "unsignedByte" => 0..=u8::MAX.into(),
+        _ => i128::MIN..=i128::MAX,
+    }
+}
+
+fn to_integer(value: &SerdeValue) -> Option<i128> {
+    match value {
+        SerdeValue::Number(number) => number
+            .as_i64()
+            .map(i128::from)
+            .or_else(|| number.as_u64().map(i128::from))
+            .or_else(|| {
+                number
+                    .as_f64()
+                    .filter(|number| number.fract() == 0.0)
+                    .map(|number| number as i128)
+            }),
+        SerdeValue::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+// JSON numbers are at most 64 bits
+fn from_integer(integer: i128) -> Option<SerdeValue> {
+    i64::try_from(integer)
+        .map(SerdeValue::from)
+        .or_else(|_| u64::try_from(integer).map(SerdeValue::from))
+        .ok()
+}
+
+fn to_date_time(timestamp: i128) -> Option<String> {
+    let date_time = if timestamp.abs() >= MAX_TIMESTAMP_SECS.into() {
+        OffsetDateTime::from_unix_timestamp_nanos(timestamp.checked_mul(1_000_000)?)
+    } else {
+        OffsetDateTime::from_unix_timestamp(timestamp.try_into().ok()?)
+    };
+
+    date_time.ok()?.format(&Rfc3339).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use iotics_grpc_client::twin::UpsertFeedWithMeta;
+    use serde_json::json;
+
+    use super::*;
+
+    fn model() -> Model {
+        let value = |label: &str, data_type: &str| FeedValue {
+            label: label.to_string(),
+            data_type: data_type.to_string(),
+            ..Default::default()
+        };
+
+        Model::new(
+            "test".to_string(),
+            "Test".to_string(),
+            Vec::new(),
+            vec![UpsertFeedWithMeta {
+                id: "status".to_string(),
+                store_last: true,
+                values: vec![
+                    value("count", "integer"),
+                    value("level", "xsd:decimal"),
+                    value("on", "boolean"),
+                    value("name", "string"),
+                    value("updated", "http://www.w3.org/2001/XMLSchema#dateTime"),
+                    value("percent", "unsignedByte"),
+                    value("delta", "negativeInteger"),
+                    value("total", "unsignedLong"),
+                ],
+                properties: Vec::new(),
+            }],
+            Vec::new(),
+        )
+    }
+
+    #[test]
+    fn coerces_the_values() {
+        let value = json!({
+            "count": "42",
+            "level": "1.5",
+            "on": "1",
+            "name": 7,
+            "updated": 1_600_000_000,
+        });
+
+        assert_eq!(
+            validate_feed_value(&model(), "status", value),
+            Ok(json!({
+                "count": 42,
+                "level": 1.5,
+                "on": true,
+                "name": "7",
+                "updated": "2020-09-13T12:26:40Z",
+            }))
+        );
+    }
+
+    #[test]
+    fn coerces_the_millisecond_timestamps() {
+        assert_eq!(
+            validate_feed_value(&model(), "status", json!({"updated": 1_600_000_000_000i64})),
+            Ok(json!({"updated": "2020-09-13T12:26:40Z"}))
+        );
+    }
+
+    #[test]
+    fn keeps_the_valid_values() {
+        let value = json!({"count": 4.0, "updated": "2020-09-13T12:26:40Z"});
+
+        assert_eq!(
+            validate_feed_value(&model(), "status", value),
+            Ok(json!({"count": 4, "updated": "2020-09-13T12:26:40Z"}))
+        );
+    }
+
+    #[test]
+    fn rejects_the_mismatches() {
+        assert_eq!(
+            validate_feed_value(&model(), "status", json!({"count": 4.2})),
+            Err(ValidationError::Mismatch {
+                feed_id: "status".to_string(),
+                field: "count".to_string(),
+                data_type: "integer".to_string(),
+                value: json!(4.2),
+            })
+        );
+        assert!(validate_feed_value(&model(), "status", json!({"on": "yes"})).is_err());
+        assert!(validate_feed_value(&model(), "status", json!({"name": null})).is_err());
+        assert!(validate_feed_value(&model(), "status", json!({"updated": "today"})).is_err());
+    }
+
+    #[test]
+    fn checks_the_integer_bounds() {
+        let value = json!({"percent": "255", "delta": -1, "total": u64::MAX});
+
+        assert_eq!(
+            validate_feed_value(&model(), "status", value.clone()),
+            Ok(json!({"percent": 255, "delta": -1, "total": u64::MAX}))
+        );
+        assert!(validate_feed_value(&model(), "status", json!({"percent": 256})).is_err());
+        assert!(validate_feed_value(&model(), "status", json!({"percent": -1})).is_err());
+        assert!(validate_feed_value(&model(), "status", json!({"delta": 0})).is_err());
+        assert!(validate_feed_value(&model(), "status", json!({"total": "-1"})).is_err());
+        assert!(
+            validate_feed_value(&model(), "status", json!({"total": "18446744073709551616"}))
+                .is_err()
+        );
+        assert!(
+            validate_feed_value(&model(), "status", json!({"count": "18446744073709551616"}))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn rejects_the_undeclared_feeds_and_fields() {
+        assert_eq!(
+            validate_feed_value(&model(), "weather", json!({})),
+            Err(ValidationError::UnknownFeed {
+                feed_id: "weather".to_string()
+            })
+        );
+        assert_eq!(
+            validate_feed_value(&model(), "status", json!(42)),
+            Err(ValidationError::NotAnObject {
+                feed_id: "status".to_string()
+            })
+        );
+        assert_eq!(
+            validate_feed_value(&model(), "status", json!({"speed": 1})),
+            Err(ValidationError::UnknownField {
+                feed_id: "status".to_string(),
+                field: "speed".to_string(),
+            })
+        );
+    }
+}
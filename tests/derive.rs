@@ -0,0 +1,85 @@
+#![cfg(feature = "derive")]
+
+use iotics_connector_engine::client::FeedValue;
+use iotics_connector_engine::feed::IoticsFeed;
+use serde_json::json;
+
+#[derive(IoticsFeed)]
+#[iotics(label = "Weather", store_last = false)]
+struct HTTPWeather {
+    #[iotics(comment = "Air temperature", unit = "http://qudt.org/vocab/unit/DEG_C")]
+    temperature: f64,
+    #[iotics(label = "wind_speed", data_type = "float")]
+    wind: Option<f32>,
+    humidity: Option<u8>,
+    #[iotics(skip)]
+    #[allow(dead_code)]
+    station_id: String,
+}
+
+#[derive(IoticsFeed)]
+#[iotics(id = "status")]
+struct Status<T: iotics_connector_engine::feed::FeedField> {
+    r#type: T,
+}
+
+#[test]
+fn declares_the_feed() {
+    let feed = HTTPWeather::feed();
+
+    assert_eq!(HTTPWeather::feed_id(), "http_weather");
+    assert_eq!(feed.id, "http_weather");
+    assert!(!feed.store_last);
+    assert_eq!(feed.properties.len(), 1);
+    assert_eq!(
+        feed.values,
+        vec![
+            FeedValue {
+                label: "temperature".to_string(),
+                comment: "Air temperature".to_string(),
+                data_type: "decimal".to_string(),
+                unit: "http://qudt.org/vocab/unit/DEG_C".to_string(),
+            },
+            FeedValue {
+                label: "wind_speed".to_string(),
+                data_type: "float".to_string(),
+                ..Default::default()
+            },
+            FeedValue {
+                label: "humidity".to_string(),
+                data_type: "integer".to_string(),
+                ..Default::default()
+            },
+        ]
+    );
+}
+
+#[test]
+fn shares_the_values() {
+    let weather = HTTPWeather {
+        temperature: 20.5,
+        wind: None,
+        humidity: Some(40),
+        station_id: "station".to_string(),
+    };
+
+    assert_eq!(
+        weather.to_feed_value(),
+        json!({"temperature": 20.5, "humidity": 40})
+    );
+}
+
+#[test]
+fn supports_the_generics_and_raw_identifiers() {
+    let feed = Status::<bool>::feed();
+
+    assert_eq!(feed.id, "status");
+    assert!(feed.store_last);
+    assert!(feed.properties.is_empty());
+    assert_eq!(feed.values[0].label, "type");
+    assert_eq!(feed.values[0].data_type, "boolean");
+    assert_eq!(
+        Status { r#type: true }.to_feed_value(),
+        json!({"type": true})
+    );
+}